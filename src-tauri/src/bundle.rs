@@ -0,0 +1,391 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::connection;
+use crate::encryption::{self, PassphraseSealed};
+use crate::error::AppError;
+use crate::DatabaseConnection;
+
+const BUNDLE_FORMAT: &str = "queryowl-connections";
+const BUNDLE_VERSION: u32 = 1;
+const BUNDLE_AAD: &str = "queryowl/connection-bundle/v1";
+
+/// A file of connections to share with another QueryOwl install, sealed with a
+/// passphrase of its own rather than the local master keys.
+#[derive(Serialize, Deserialize)]
+struct BundleFile {
+    format: String,
+    version: u32,
+    #[serde(flatten)]
+    sealed: PassphraseSealed,
+}
+
+/// What the passphrase protects: connections with their secrets in plain text,
+/// or without them.
+#[derive(Serialize, Deserialize)]
+struct BundleContents {
+    exported_at: String,
+    connections: Vec<DatabaseConnection>,
+}
+
+/// What to do with a bundled connection whose id or name is already saved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    Skip,
+    /// Replace the saved connection, keeping its id and any secrets the bundle leaves out
+    Overwrite,
+    /// Save the bundled one alongside under a new id and name
+    Duplicate,
+}
+
+/// A bundled connection as shown before importing.
+#[derive(Debug, Serialize)]
+pub struct BundleEntry {
+    pub id: String,
+    pub name: String,
+    pub host: String,
+    pub database: String,
+    pub has_password: bool,
+    /// Name of the saved connection with the same id or name
+    pub conflicts_with: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BundleImport {
+    pub imported: Vec<DatabaseConnection>,
+    /// Ids of saved connections that were replaced
+    pub overwritten: Vec<String>,
+    /// Entries that were not imported, and why
+    pub skipped: Vec<String>,
+}
+
+/// Write the connections `ids` to `path`, sealed with `passphrase`. Without
+/// `include_passwords` the database and SSH secrets are left out.
+pub fn export(
+    app: &tauri::AppHandle,
+    path: &Path,
+    ids: &[String],
+    passphrase: &str,
+    include_passwords: bool,
+) -> Result<usize, AppError> {
+    let connections = connection::load_connections(app)?
+        .into_iter()
+        .filter(|conn| ids.contains(&conn.id))
+        .map(|conn| bundled(conn, include_passwords))
+        .collect::<Result<Vec<_>, _>>()?;
+    if connections.is_empty() {
+        return Err(AppError::InvalidInput("No connections selected to export".to_string()));
+    }
+
+    let count = connections.len();
+    let contents = BundleContents {
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        connections,
+    };
+    let json = serde_json::to_string_pretty(&seal(&contents, passphrase)?)
+        .map_err(|e| AppError::Storage(format!("Failed to serialize bundle: {}", e)))?;
+    std::fs::write(path, json)
+        .map_err(|e| AppError::Io(format!("Failed to write {}: {}", path.display(), e)))?;
+
+    Ok(count)
+}
+
+fn seal(contents: &BundleContents, passphrase: &str) -> Result<BundleFile, AppError> {
+    let json = serde_json::to_vec(contents)
+        .map_err(|e| AppError::Storage(format!("Failed to serialize connections: {}", e)))?;
+    Ok(BundleFile {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        sealed: encryption::seal_with_passphrase(passphrase, &json, BUNDLE_AAD)?,
+    })
+}
+
+/// A stored connection with its secrets decrypted or dropped.
+fn bundled(mut conn: DatabaseConnection, include_passwords: bool) -> Result<DatabaseConnection, AppError> {
+    if include_passwords {
+        conn.password = conn.password
+            .map(|password| encryption::decrypt_password(&password, &conn.id, encryption::PASSWORD_FIELD))
+            .transpose()
+            .map_err(|e| AppError::encryption("Failed to decrypt password", e))?;
+        conn.ssh_tunnel = conn.ssh_tunnel.map(|ssh| ssh.decrypted(&conn.id)).transpose()?;
+    } else {
+        conn.password = None;
+        if let Some(ssh) = &mut conn.ssh_tunnel {
+            ssh.password = None;
+            ssh.passphrase = None;
+        }
+    }
+    conn.last_connected = None;
+    Ok(conn)
+}
+
+fn read(path: &Path, passphrase: &str) -> Result<BundleContents, AppError> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| AppError::Io(format!("Failed to read {}: {}", path.display(), e)))?;
+    let file: BundleFile = serde_json::from_str(&json)
+        .ok()
+        .filter(|file: &BundleFile| file.format == BUNDLE_FORMAT)
+        .ok_or_else(|| AppError::InvalidInput(format!("{} is not a QueryOwl connection bundle", path.display())))?;
+    open(&file, passphrase)
+}
+
+fn open(file: &BundleFile, passphrase: &str) -> Result<BundleContents, AppError> {
+    if file.version > BUNDLE_VERSION {
+        return Err(AppError::InvalidInput("The bundle was made by a newer version of QueryOwl".to_string()));
+    }
+
+    let json = encryption::open_with_passphrase(&file.sealed, passphrase, BUNDLE_AAD)?;
+    serde_json::from_slice(&json)
+        .map_err(|e| AppError::InvalidInput(format!("Failed to parse bundled connections: {}", e)))
+}
+
+/// The saved connection a bundled one collides with, by id first, then by name.
+fn conflict<'s>(stored: &'s [DatabaseConnection], conn: &DatabaseConnection) -> Option<&'s DatabaseConnection> {
+    stored.iter().find(|s| s.id == conn.id)
+        .or_else(|| stored.iter().find(|s| s.name == conn.name))
+}
+
+pub fn preview(app: &tauri::AppHandle, path: &Path, passphrase: &str) -> Result<Vec<BundleEntry>, AppError> {
+    let stored = connection::load_connections(app)?;
+    Ok(read(path, passphrase)?.connections.into_iter()
+        .map(|conn| BundleEntry {
+            conflicts_with: conflict(&stored, &conn).map(|s| s.name.clone()),
+            has_password: conn.password.as_deref().is_some_and(|p| !p.is_empty()),
+            id: conn.id,
+            name: conn.name,
+            host: conn.host,
+            database: conn.database,
+        })
+        .collect())
+}
+
+/// Save the connections in the bundle at `path`, resolving collisions with
+/// saved connections by `policy`. Secrets are encrypted with the local keys.
+pub fn import(
+    app: &tauri::AppHandle,
+    path: &Path,
+    passphrase: &str,
+    policy: ConflictPolicy,
+) -> Result<BundleImport, AppError> {
+    let contents = read(path, passphrase)?;
+    let _store_lock = connection::lock_store();
+    let mut stored = connection::load_connections(app)?;
+    let result = merge(&mut stored, contents.connections, policy, encrypt_secrets)?;
+    connection::save_connections(app, &stored)?;
+    Ok(result)
+}
+
+/// Add bundled connections to the stored ones. `encrypt` seals a connection's
+/// secrets once its final id is known.
+fn merge(
+    stored: &mut Vec<DatabaseConnection>,
+    bundled: Vec<DatabaseConnection>,
+    policy: ConflictPolicy,
+    encrypt: impl Fn(DatabaseConnection) -> Result<DatabaseConnection, AppError>,
+) -> Result<BundleImport, AppError> {
+    let mut result = BundleImport {
+        imported: Vec::new(),
+        overwritten: Vec::new(),
+        skipped: Vec::new(),
+    };
+    let now = chrono::Utc::now().to_rfc3339();
+
+    for mut conn in bundled {
+        if let Err(e) = connection::parse_hosts(&conn.host, conn.port) {
+            result.skipped.push(format!("{}: {}", conn.name, e));
+            continue;
+        }
+
+        let existing = conflict(stored, &conn).map(|s| s.id.clone());
+        let replace = match (&existing, policy) {
+            (None, _) => None,
+            (Some(_), ConflictPolicy::Skip) => {
+                result.skipped.push(format!("{}: already saved", conn.name));
+                continue;
+            },
+            (Some(id), ConflictPolicy::Overwrite) => stored.iter().position(|s| s.id == *id),
+            (Some(_), ConflictPolicy::Duplicate) => {
+                conn.id = Uuid::new_v4().to_string();
+                if stored.iter().any(|s| s.name == conn.name) {
+                    conn.name = unique_name(stored, &conn.name);
+                }
+                None
+            },
+        };
+
+        if let Some(index) = replace {
+            conn.id = stored[index].id.clone();
+        }
+        let mut conn = encrypt(conn)?;
+
+        match replace {
+            Some(index) => {
+                let previous = &stored[index];
+                conn.created_at = previous.created_at.clone();
+                conn.last_connected = previous.last_connected.clone();
+                // A bundle without passwords keeps the ones saved here
+                if conn.password.is_none() {
+                    conn.password = previous.password.clone();
+                }
+                if let (Some(ssh), Some(previous_ssh)) = (&mut conn.ssh_tunnel, &previous.ssh_tunnel) {
+                    ssh.password = ssh.password.take().or_else(|| previous_ssh.password.clone());
+                    ssh.passphrase = ssh.passphrase.take().or_else(|| previous_ssh.passphrase.clone());
+                }
+                result.overwritten.push(conn.id.clone());
+                stored[index] = conn.clone();
+            },
+            None => {
+                conn.created_at = now.clone();
+                conn.last_connected = None;
+                stored.push(conn.clone());
+            },
+        }
+        result.imported.push(conn);
+    }
+
+    Ok(result)
+}
+
+/// The connection with its secrets encrypted with the local keys.
+fn encrypt_secrets(mut conn: DatabaseConnection) -> Result<DatabaseConnection, AppError> {
+    conn.password = conn.password
+        .map(|password| encryption::encrypt_password(&password, &conn.id, encryption::PASSWORD_FIELD))
+        .transpose()
+        .map_err(|e| AppError::encryption("Failed to encrypt password", e))?;
+    conn.ssh_tunnel = conn.ssh_tunnel.map(|ssh| ssh.encrypted(&conn.id)).transpose()?;
+    Ok(conn)
+}
+
+/// `name (2)`, `name (3)`, ... whichever is free first.
+fn unique_name(stored: &[DatabaseConnection], name: &str) -> String {
+    (2..)
+        .map(|n| format!("{} ({})", name, n))
+        .find(|candidate| stored.iter().all(|s| s.name != *candidate))
+        .unwrap_or_else(|| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection(id: &str, name: &str, password: Option<&str>) -> DatabaseConnection {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": name,
+            "host": "db",
+            "port": 5432,
+            "database": "app",
+            "username": "app",
+            "password": password,
+            "created_at": "2025-01-01T00:00:00Z",
+            "last_connected": "2025-01-02T00:00:00Z",
+        })).unwrap()
+    }
+
+    // Stands in for the local keys, which tests don't have
+    fn encrypt(mut conn: DatabaseConnection) -> Result<DatabaseConnection, AppError> {
+        conn.password = conn.password.map(|password| format!("sealed:{}:{}", conn.id, password));
+        Ok(conn)
+    }
+
+    fn stored() -> Vec<DatabaseConnection> {
+        vec![
+            connection("a", "Production", Some("sealed:a:old")),
+            connection("b", "Staging", Some("sealed:b:old")),
+        ]
+    }
+
+    #[test]
+    fn bundles_open_with_their_passphrase_only() {
+        let contents = BundleContents {
+            exported_at: "2025-01-01T00:00:00Z".to_string(),
+            connections: vec![connection("a", "Production", Some("s3cret"))],
+        };
+        let file = seal(&contents, "correct horse").unwrap();
+        assert!(!serde_json::to_string(&file).unwrap().contains("s3cret"));
+
+        let opened = open(&file, "correct horse").unwrap();
+        assert_eq!(opened.connections[0].name, "Production");
+        assert_eq!(opened.connections[0].password.as_deref(), Some("s3cret"));
+
+        assert!(matches!(open(&file, "wrong horse"), Err(AppError::Authentication(_))));
+    }
+
+    #[test]
+    fn skip_leaves_conflicts_alone() {
+        let mut stored = stored();
+        let bundled = vec![
+            connection("a", "Renamed", Some("new")),
+            connection("x", "Staging", Some("new")),
+            connection("y", "New", Some("new")),
+        ];
+        let result = merge(&mut stored, bundled, ConflictPolicy::Skip, encrypt).unwrap();
+
+        assert_eq!(result.skipped, ["Renamed: already saved", "Staging: already saved"]);
+        assert_eq!(result.imported.len(), 1);
+        assert_eq!(stored.len(), 3);
+        assert_eq!(stored[0].name, "Production");
+        assert_eq!(stored[2].password.as_deref(), Some("sealed:y:new"));
+        assert_eq!(stored[2].last_connected, None);
+    }
+
+    #[test]
+    fn overwrite_replaces_by_id_then_name() {
+        let mut stored = stored();
+        let bundled = vec![
+            connection("a", "Production EU", Some("new")),
+            connection("x", "Staging", Some("new")),
+        ];
+        let result = merge(&mut stored, bundled, ConflictPolicy::Overwrite, encrypt).unwrap();
+
+        assert_eq!(result.overwritten, ["a", "b"]);
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[0].name, "Production EU");
+        // The saved connection keeps its id, and secrets are sealed for it
+        assert_eq!(stored[1].id, "b");
+        assert_eq!(stored[1].password.as_deref(), Some("sealed:b:new"));
+        assert_eq!(stored[1].last_connected.as_deref(), Some("2025-01-02T00:00:00Z"));
+    }
+
+    #[test]
+    fn overwrite_keeps_secrets_the_bundle_leaves_out() {
+        let mut stored = stored();
+        merge(&mut stored, vec![connection("a", "Production", None)], ConflictPolicy::Overwrite, encrypt).unwrap();
+        assert_eq!(stored[0].password.as_deref(), Some("sealed:a:old"));
+    }
+
+    #[test]
+    fn duplicate_saves_alongside_under_a_new_id_and_name() {
+        let mut stored = stored();
+        stored.push(connection("c", "Production (2)", None));
+        let bundled = vec![
+            connection("a", "Production", Some("new")),
+            connection("b", "Elsewhere", None),
+        ];
+        let result = merge(&mut stored, bundled, ConflictPolicy::Duplicate, encrypt).unwrap();
+
+        assert!(result.overwritten.is_empty());
+        assert_eq!(stored.len(), 5);
+        let copy = &stored[3];
+        assert_ne!(copy.id, "a");
+        assert_eq!(copy.name, "Production (3)");
+        assert_eq!(copy.password, Some(format!("sealed:{}:new", copy.id)));
+        // A clash by id alone keeps the bundled name
+        assert_ne!(stored[4].id, "b");
+        assert_eq!(stored[4].name, "Elsewhere");
+        assert_eq!(stored[0].password.as_deref(), Some("sealed:a:old"));
+    }
+
+    #[test]
+    fn invalid_hosts_are_skipped() {
+        let mut stored = stored();
+        let mut bad = connection("x", "Bad", None);
+        bad.host = "a,,b".to_string();
+        let result = merge(&mut stored, vec![bad], ConflictPolicy::Duplicate, encrypt).unwrap();
+        assert!(result.imported.is_empty());
+        assert_eq!(result.skipped.len(), 1);
+        assert_eq!(stored.len(), 2);
+    }
+}
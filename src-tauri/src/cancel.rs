@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio_postgres::CancelToken;
+
+use crate::query_error::QueryError;
+
+pub const CANCELED_MESSAGE: &str = "Query canceled by user";
+
+struct RunningQuery {
+    connection_id: String,
+    token: CancelToken,
+    canceled: Arc<AtomicBool>,
+}
+
+/// Cancel tokens of in-flight queries, keyed by the query id handed to the frontend.
+pub struct RunningQueries {
+    queries: Mutex<HashMap<String, RunningQuery>>,
+}
+
+impl RunningQueries {
+    pub fn new() -> Self {
+        RunningQueries {
+            queries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Track a query until the returned guard is dropped.
+    pub fn register(&self, query_id: &str, connection_id: &str, token: CancelToken) -> QueryGuard<'_> {
+        let canceled = Arc::new(AtomicBool::new(false));
+        self.queries.lock().unwrap().insert(query_id.to_string(), RunningQuery {
+            connection_id: connection_id.to_string(),
+            token,
+            canceled: canceled.clone(),
+        });
+
+        QueryGuard {
+            queries: self,
+            query_id: query_id.to_string(),
+            canceled,
+        }
+    }
+
+    /// Mark the query as canceled and hand back what is needed to send the
+    /// cancel request: the connection id (for its TLS settings) and the token.
+    pub fn begin_cancel(&self, query_id: &str) -> Option<(String, CancelToken)> {
+        let queries = self.queries.lock().unwrap();
+        let query = queries.get(query_id)?;
+        query.canceled.store(true, Ordering::SeqCst);
+        Some((query.connection_id.clone(), query.token.clone()))
+    }
+}
+
+pub struct QueryGuard<'a> {
+    queries: &'a RunningQueries,
+    query_id: String,
+    canceled: Arc<AtomicBool>,
+}
+
+impl QueryGuard<'_> {
+    pub fn query_id(&self) -> &str {
+        &self.query_id
+    }
+
+    pub fn is_canceled(&self) -> bool {
+        self.canceled.load(Ordering::SeqCst)
+    }
+
+    /// Replace the server's error with a clean message when the user canceled.
+    pub fn map_error<E: std::fmt::Display>(&self, context: &str, e: E) -> String {
+        if self.is_canceled() {
+            CANCELED_MESSAGE.to_string()
+        } else {
+            format!("{}: {}", context, e)
+        }
+    }
+
+    /// Like `map_error`, keeping the server's error fields.
+    pub fn query_error(&self, context: &str, e: tokio_postgres::Error) -> QueryError {
+        QueryError::new(self.map_error(context, &e), &e)
+    }
+}
+
+impl Drop for QueryGuard<'_> {
+    fn drop(&mut self) {
+        self.queries.queries.lock().unwrap().remove(&self.query_id);
+    }
+}
@@ -0,0 +1,561 @@
+use postgres_native_tls::MakeTlsConnector;
+use serde::{Deserialize, Serialize};
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tauri::Manager;
+use tauri_plugin_store::StoreExt;
+use tokio::task::JoinHandle;
+use tokio_postgres::{AsyncMessage, Client, Config};
+
+use crate::error::AppError;
+use crate::notices::{Notice, Notices};
+use crate::safety::SafetyPolicy;
+use crate::tls::TlsSettings;
+use crate::tunnel::{SshTunnel, Tunnel, TunnelManager};
+use crate::{encryption, DatabaseConnection};
+
+const CONNECTIONS_STORE: &str = "connections.json";
+// Version of the layout of connections.json, bumped by `migrate_store`
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+const SCHEMA_VERSION: u64 = 2;
+const DEFAULT_APPLICATION_NAME: &str = "QueryOwl";
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// Sent with the startup options of read-only connections
+const READ_ONLY_OPTION: &str = "-c default_transaction_read_only=on";
+
+/// libpq-compatible `target_session_attrs` values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TargetSessionAttrs {
+    Any,
+    ReadWrite,
+    ReadOnly,
+}
+
+impl TargetSessionAttrs {
+    fn config_value(&self) -> tokio_postgres::config::TargetSessionAttrs {
+        match self {
+            TargetSessionAttrs::Any => tokio_postgres::config::TargetSessionAttrs::Any,
+            TargetSessionAttrs::ReadWrite => tokio_postgres::config::TargetSessionAttrs::ReadWrite,
+            TargetSessionAttrs::ReadOnly => tokio_postgres::config::TargetSessionAttrs::ReadOnly,
+        }
+    }
+}
+
+/// Optional connection parameters, stored flattened into the connection.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConnectionOptions {
+    /// Shown in `pg_stat_activity`; defaults to "QueryOwl"
+    pub application_name: Option<String>,
+    /// Seconds to wait for the server; defaults to 10
+    pub connect_timeout: Option<u64>,
+    pub keepalives: Option<bool>,
+    /// Seconds of inactivity before the first TCP keepalive is sent
+    pub keepalives_idle: Option<u64>,
+    pub target_session_attrs: Option<TargetSessionAttrs>,
+    /// Command-line options sent to the server, e.g. `-c search_path=app,public`
+    pub options: Option<String>,
+    pub safety_policy: Option<SafetyPolicy>,
+}
+
+/// What kind of server a connection points at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Environment {
+    Development,
+    Staging,
+    Production,
+}
+
+/// How a connection is organized in the list, stored flattened into the connection.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConnectionLabels {
+    /// `/`-separated path, e.g. `Team/Billing`
+    pub folder: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub environment: Option<Environment>,
+}
+
+impl ConnectionLabels {
+    /// Trimmed, with an empty folder dropped and tags deduplicated ignoring case.
+    pub fn normalized(self) -> Self {
+        let folder = self.folder
+            .map(|folder| folder.split('/').map(str::trim).filter(|part| !part.is_empty()).collect::<Vec<_>>().join("/"))
+            .filter(|folder| !folder.is_empty());
+
+        let mut tags: Vec<String> = Vec::new();
+        for tag in self.tags.iter().map(|tag| tag.trim()).filter(|tag| !tag.is_empty()) {
+            if !tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                tags.push(tag.to_string());
+            }
+        }
+
+        ConnectionLabels { folder, tags, environment: self.environment }
+    }
+
+    fn in_folder(&self, folder: &str) -> bool {
+        let folder = folder.trim_matches('/');
+        self.folder.as_deref().is_some_and(|own| {
+            own == folder || own.strip_prefix(folder).is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+
+    fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag.trim()))
+    }
+}
+
+/// Which saved connections `get_stored_connections` returns. Unset fields match everything.
+#[derive(Debug, Default, Deserialize)]
+pub struct ConnectionFilter {
+    /// Includes subfolders
+    pub folder: Option<String>,
+    pub tag: Option<String>,
+    pub environment: Option<Environment>,
+}
+
+impl ConnectionFilter {
+    fn matches(&self, connection: &DatabaseConnection) -> bool {
+        let labels = &connection.labels;
+        self.folder.as_deref().is_none_or(|folder| labels.in_folder(folder))
+            && self.tag.as_deref().is_none_or(|tag| labels.has_tag(tag))
+            && self.environment.is_none_or(|environment| labels.environment == Some(environment))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionSort {
+    Name,
+    /// By folder, connections without one last, then by name
+    Folder,
+    /// Most recently used first, never used last
+    LastConnected,
+}
+
+/// The connections matching `filter`, in `sort` order or else as stored.
+pub fn filter_and_sort(
+    connections: Vec<DatabaseConnection>,
+    filter: &ConnectionFilter,
+    sort: Option<ConnectionSort>,
+) -> Vec<DatabaseConnection> {
+    let mut connections: Vec<_> = connections.into_iter()
+        .filter(|connection| filter.matches(connection))
+        .collect();
+
+    let by_name = |a: &DatabaseConnection, b: &DatabaseConnection| a.name.to_lowercase().cmp(&b.name.to_lowercase());
+    match sort {
+        Some(ConnectionSort::Name) => connections.sort_by(by_name),
+        Some(ConnectionSort::Folder) => connections.sort_by(|a, b| {
+            let folder = |c: &DatabaseConnection| (c.labels.folder.is_none(), c.labels.folder.as_ref().map(|f| f.to_lowercase()));
+            folder(a).cmp(&folder(b)).then_with(|| by_name(a, b))
+        }),
+        Some(ConnectionSort::LastConnected) => connections.sort_by(|a, b| {
+            b.last_connected.cmp(&a.last_connected).then_with(|| by_name(a, b))
+        }),
+        None => {},
+    }
+    connections
+}
+
+// Held while connections.json is read, changed and written back, and while the
+// master keys change, so no write is lost and no secret is sealed with a dropped key
+static STORE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Serialize changes to the saved connections and the keys sealing their secrets.
+pub fn lock_store() -> MutexGuard<'static, ()> {
+    STORE_LOCK.lock().unwrap()
+}
+
+/// All saved connections, as stored (passwords encrypted).
+pub fn load_connections(app: &tauri::AppHandle) -> Result<Vec<DatabaseConnection>, AppError> {
+    let store = app.store_builder(CONNECTIONS_STORE).build()
+        .map_err(|e| AppError::Storage(format!("Failed to build store: {}", e)))?;
+
+    match store.get("connections") {
+        Some(value) => serde_json::from_value(value)
+            .map_err(|e| AppError::Storage(format!("Failed to deserialize connections: {}", e))),
+        None => Ok(vec![]),
+    }
+}
+
+/// Replace the saved connections. Hold `lock_store` from loading them until they are saved.
+pub fn save_connections(app: &tauri::AppHandle, connections: &[DatabaseConnection]) -> Result<(), AppError> {
+    let store = app.store_builder(CONNECTIONS_STORE).build()
+        .map_err(|e| AppError::Storage(format!("Failed to build store: {}", e)))?;
+
+    let value = serde_json::to_value(connections)
+        .map_err(|e| AppError::Storage(format!("Failed to serialize connections: {}", e)))?;
+
+    store.set("connections", value);
+    store.save().map_err(|e| AppError::Storage(format!("Failed to save store: {}", e)))
+}
+
+/// Bring connections.json up to the current layout. Version 2 added folders,
+/// tags and the environment.
+pub fn migrate_store(app: &tauri::AppHandle) -> Result<(), AppError> {
+    let store = app.store_builder(CONNECTIONS_STORE).build()
+        .map_err(|e| AppError::Storage(format!("Failed to build store: {}", e)))?;
+
+    let version = store.get(SCHEMA_VERSION_KEY).and_then(|version| version.as_u64()).unwrap_or(1);
+    if version >= SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    if let Some(serde_json::Value::Array(mut connections)) = store.get("connections") {
+        for conn in connections.iter_mut().filter_map(|conn| conn.as_object_mut()) {
+            conn.entry("folder").or_insert(serde_json::Value::Null);
+            conn.entry("tags").or_insert_with(|| serde_json::json!([]));
+            conn.entry("environment").or_insert(serde_json::Value::Null);
+        }
+        store.set("connections", connections);
+    }
+    store.set(SCHEMA_VERSION_KEY, SCHEMA_VERSION);
+    store.save().map_err(|e| AppError::Storage(format!("Failed to save migrated connections: {}", e)))?;
+
+    log::info!("Migrated saved connections from schema version {} to {}", version, SCHEMA_VERSION);
+    Ok(())
+}
+
+/// Look up a saved connection by id. Every command that works on a saved
+/// connection starts here.
+pub fn resolve_connection(app: &tauri::AppHandle, connection_id: &str) -> Result<DatabaseConnection, AppError> {
+    load_connections(app)?
+        .into_iter()
+        .find(|c| c.id == connection_id)
+        .ok_or_else(|| AppError::NotFound("Connection not found".to_string()))
+}
+
+/// One entry of a connection's host list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostSpec {
+    Tcp { host: String, port: u16 },
+    /// Directory holding the server's Unix domain socket, `.s.PGSQL.<port>`
+    Socket { dir: String, port: u16 },
+}
+
+/// Parse a connection's `host`: a host name or address, a socket directory
+/// (starting with `/`), or a comma-separated list of these. List entries may
+/// carry their own port as `host:port` or `[ipv6]:port`; the rest use `port`.
+pub fn parse_hosts(host: &str, port: u16) -> Result<Vec<HostSpec>, AppError> {
+    host.split(',').map(str::trim).map(|spec| {
+        if spec.is_empty() {
+            return Err(AppError::InvalidInput(format!("Empty entry in host list \"{}\"", host)));
+        }
+        if spec.starts_with('/') {
+            return Ok(HostSpec::Socket { dir: spec.to_string(), port });
+        }
+
+        let (name, spec_port) = match spec.strip_prefix('[') {
+            Some(bracketed) => {
+                let (name, after) = bracketed.split_once(']')
+                    .ok_or_else(|| AppError::InvalidInput(format!("Unterminated IPv6 address in \"{}\"", spec)))?;
+                (name, after.strip_prefix(':'))
+            },
+            // A bare IPv6 address has several colons and no port
+            None => match spec.split_once(':') {
+                Some((name, spec_port)) if !spec_port.contains(':') => (name, Some(spec_port)),
+                _ => (spec, None),
+            },
+        };
+
+        let port = match spec_port {
+            Some(spec_port) => spec_port.parse()
+                .map_err(|_| AppError::InvalidInput(format!("Invalid port in host \"{}\"", spec)))?,
+            None => port,
+        };
+        Ok(HostSpec::Tcp { host: name.to_string(), port })
+    }).collect()
+}
+
+/// Give each entry of a host list the port at its position in a libpq-style
+/// port list. A single port applies to every host, so it stays the connection's
+/// port; otherwise there must be one per host. Returns the host list and port.
+pub fn apply_port_list(host: &str, ports: &[u16]) -> Result<(String, u16), AppError> {
+    let [first, rest @ ..] = ports else {
+        return Err(AppError::InvalidInput("Empty port list".to_string()));
+    };
+    if rest.is_empty() {
+        return Ok((host.to_string(), *first));
+    }
+
+    let specs = parse_hosts(host, *first)?;
+    if specs.len() != ports.len() {
+        return Err(AppError::InvalidInput(format!("{} ports given for {} hosts", ports.len(), specs.len())));
+    }
+    let entries = specs.iter().zip(ports).map(|(spec, port)| match spec {
+        HostSpec::Tcp { host, .. } if host.contains(':') => Ok(format!("[{}]:{}", host, port)),
+        HostSpec::Tcp { host, .. } => Ok(format!("{}:{}", host, port)),
+        // Socket directories in a list use the connection's port
+        HostSpec::Socket { dir, .. } if port == first => Ok(dir.clone()),
+        HostSpec::Socket { dir, .. } => Err(AppError::InvalidInput(format!(
+            "Socket directory \"{}\" in a host list can't have a port of its own", dir
+        ))),
+    }).collect::<Result<Vec<_>, AppError>>()?;
+    Ok((entries.join(","), *first))
+}
+
+/// The one TCP host a tunnel can forward to.
+fn tunnel_endpoint(host: &str, port: u16) -> Result<(String, u16), AppError> {
+    match parse_hosts(host, port)?.as_slice() {
+        [HostSpec::Tcp { host, port }] => Ok((host.clone(), *port)),
+        _ => Err(AppError::InvalidInput(
+            "An SSH tunnel needs a single host name, not a host list or socket directory".to_string(),
+        )),
+    }
+}
+
+/// Where to connect and as whom, with the password in plain text.
+pub struct ConnectTarget<'a> {
+    pub host: &'a str,
+    pub port: u16,
+    pub database: &'a str,
+    pub username: &'a str,
+    pub password: String,
+    pub tls: TlsSettings<'a>,
+    pub options: &'a ConnectionOptions,
+    /// Set when the server is reached through an SSH tunnel
+    pub tunnel: Option<Arc<Tunnel>>,
+}
+
+impl ConnectTarget<'_> {
+    /// The typed config, so passwords and names with spaces, quotes or `=` need no escaping.
+    pub fn config(&self) -> Result<Config, AppError> {
+        let mut config = Config::new();
+
+        if let Some(tunnel) = &self.tunnel {
+            // Connect to the tunnel's local end; the host name is still used to verify the certificate
+            let (host, _) = tunnel_endpoint(self.host, self.port)?;
+            config.host(host).hostaddr(Ipv4Addr::LOCALHOST.into()).port(tunnel.local_port());
+        } else {
+            // Hosts are tried in order until one accepts the connection and matches target_session_attrs
+            for spec in parse_hosts(self.host, self.port)? {
+                match spec {
+                    HostSpec::Tcp { host, port } => config.host(host).port(port),
+                    HostSpec::Socket { dir, port } => add_socket(&mut config, &dir)?.port(port),
+                };
+            }
+        }
+
+        config
+            .dbname(self.database)
+            .user(self.username)
+            .password(&self.password)
+            .ssl_mode(self.tls.mode().config_mode())
+            .application_name(non_empty(&self.options.application_name).unwrap_or(DEFAULT_APPLICATION_NAME))
+            .connect_timeout(self.options.connect_timeout.map_or(DEFAULT_CONNECT_TIMEOUT, Duration::from_secs));
+
+        if let Some(keepalives) = self.options.keepalives {
+            config.keepalives(keepalives);
+        }
+        if let Some(idle) = self.options.keepalives_idle {
+            config.keepalives_idle(Duration::from_secs(idle));
+        }
+        if let Some(attrs) = self.options.target_session_attrs {
+            config.target_session_attrs(attrs.config_value());
+        }
+        let mut options = non_empty(&self.options.options).map(str::to_string);
+        if self.options.safety_policy == Some(SafetyPolicy::ReadOnly) {
+            // Last, so it wins over a conflicting `-c` in the user's options
+            options = Some(options.map_or(READ_ONLY_OPTION.to_string(), |options| format!("{} {}", options, READ_ONLY_OPTION)));
+        }
+        if let Some(options) = options {
+            config.options(&options);
+        }
+        Ok(config)
+    }
+
+    /// Open a tunnel just for this target, e.g. to test unsaved settings. It
+    /// closes when the client opened with it disconnects.
+    pub async fn with_tunnel(mut self, ssh: Option<&SshTunnel>) -> Result<Self, AppError> {
+        if let Some(ssh) = ssh {
+            let (host, port) = tunnel_endpoint(self.host, self.port)?;
+            self.tunnel = Some(Arc::new(Tunnel::open(ssh, &host, port).await?));
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(unix)]
+fn add_socket<'c>(config: &'c mut Config, dir: &str) -> Result<&'c mut Config, AppError> {
+    Ok(config.host_path(dir))
+}
+
+#[cfg(not(unix))]
+fn add_socket<'c>(_config: &'c mut Config, dir: &str) -> Result<&'c mut Config, AppError> {
+    Err(AppError::InvalidInput(format!("Unix domain sockets ({}) are not supported on this platform", dir)))
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|value| !value.is_empty())
+}
+
+impl<'a> ConnectTarget<'a> {
+    /// The target of a saved connection, decrypting its password.
+    pub fn from_stored(connection: &'a DatabaseConnection) -> Result<Self, AppError> {
+        let password = match &connection.password {
+            Some(encrypted) => {
+                encryption::decrypt_password(encrypted, &connection.id, encryption::PASSWORD_FIELD)
+                    .map_err(|e| AppError::encryption("Failed to decrypt password", e))?
+            },
+            None => String::new(),
+        };
+
+        Ok(ConnectTarget {
+            host: &connection.host,
+            port: connection.port,
+            database: &connection.database,
+            username: &connection.username,
+            password,
+            tls: connection.tls_settings(),
+            options: &connection.connect_options,
+            tunnel: None,
+        })
+    }
+}
+
+/// The TLS connector for a saved connection, e.g. to send a cancel request.
+pub fn tls_connector(connection: &DatabaseConnection) -> Result<MakeTlsConnector, AppError> {
+    connection.tls_settings().connector().map_err(AppError::Tls)
+}
+
+/// Open a client and spawn the task driving its connection, which finishes when
+/// the server closes the connection. Server notices are passed to `notices`.
+pub async fn open(
+    target: &ConnectTarget<'_>,
+    notices: Option<Notices>,
+) -> Result<(Client, JoinHandle<Result<(), String>>), AppError> {
+    let tls = target.tls.connector().map_err(AppError::Tls)?;
+
+    let (client, mut conn) = target.config()?.connect(tls).await
+        .map_err(|e| AppError::connect("Connection failed", e))?;
+
+    // Spawn connection handler, polling for messages so notices aren't dropped
+    let tunnel = target.tunnel.clone();
+    let driver = tokio::spawn(async move {
+        // Keep the tunnel open for as long as the connection is
+        let _tunnel = tunnel;
+        loop {
+            match futures::future::poll_fn(|cx| conn.poll_message(cx)).await {
+                Some(Ok(AsyncMessage::Notice(notice))) => {
+                    if let Some(notices) = &notices {
+                        notices.push(Notice::from(&notice));
+                    }
+                },
+                Some(Ok(_)) => {},
+                Some(Err(e)) => {
+                    log::warn!("Connection error: {}", e);
+                    return Err(e.to_string());
+                },
+                None => return Ok(()),
+            }
+        }
+    });
+
+    Ok((client, driver))
+}
+
+/// Open a client for a saved connection along with the buffer its server notices
+/// go to. Connections behind a bastion share one tunnel.
+pub async fn connect(
+    app: &tauri::AppHandle,
+    connection: &DatabaseConnection,
+) -> Result<(Client, Notices, JoinHandle<Result<(), String>>), AppError> {
+    let mut target = ConnectTarget::from_stored(connection)?;
+    if let Some(ssh) = &connection.ssh_tunnel {
+        let (host, port) = tunnel_endpoint(&connection.host, connection.port)?;
+        let tunnels = app.state::<TunnelManager>();
+        let ssh = ssh.decrypted(&connection.id)?;
+        target.tunnel = Some(tunnels.get(&connection.id, &ssh, &host, port).await?);
+    }
+    let notices = Notices::new(app, &connection.id);
+    let (client, driver) = open(&target, Some(notices.clone())).await?;
+    Ok((client, notices, driver))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcp(host: &str, port: u16) -> HostSpec {
+        HostSpec::Tcp { host: host.to_string(), port }
+    }
+
+    #[test]
+    fn single_host_uses_the_connection_port() {
+        assert_eq!(parse_hosts("db.example.com", 5432).unwrap(), vec![tcp("db.example.com", 5432)]);
+        assert_eq!(parse_hosts(" 10.0.0.1 ", 5433).unwrap(), vec![tcp("10.0.0.1", 5433)]);
+        assert_eq!(
+            parse_hosts("/var/run/postgresql", 5432).unwrap(),
+            vec![HostSpec::Socket { dir: "/var/run/postgresql".to_string(), port: 5432 }]
+        );
+    }
+
+    #[test]
+    fn host_list_entries_may_carry_their_own_port() {
+        assert_eq!(
+            parse_hosts("primary:5433, replica, /tmp", 5432).unwrap(),
+            vec![
+                tcp("primary", 5433),
+                tcp("replica", 5432),
+                HostSpec::Socket { dir: "/tmp".to_string(), port: 5432 },
+            ]
+        );
+    }
+
+    #[test]
+    fn ipv6_addresses() {
+        assert_eq!(parse_hosts("::1", 5432).unwrap(), vec![tcp("::1", 5432)]);
+        assert_eq!(parse_hosts("[::1]", 5432).unwrap(), vec![tcp("::1", 5432)]);
+        assert_eq!(
+            parse_hosts("[fe80::1]:6432,[::1]", 5432).unwrap(),
+            vec![tcp("fe80::1", 6432), tcp("::1", 5432)]
+        );
+        assert!(matches!(parse_hosts("[::1", 5432), Err(AppError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn empty_entries_are_errors() {
+        assert!(matches!(parse_hosts("", 5432), Err(AppError::InvalidInput(_))));
+        assert!(matches!(parse_hosts("a,,b", 5432), Err(AppError::InvalidInput(_))));
+        assert!(matches!(parse_hosts("a, ", 5432), Err(AppError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn invalid_ports_are_errors() {
+        for host in ["db:", "db:port", "db:65536", "db:-1", "[::1]:x", "a:5432,b:99999"] {
+            match parse_hosts(host, 5432) {
+                Err(AppError::InvalidInput(message)) => assert!(message.contains("Invalid port"), "{}", message),
+                other => panic!("{} parsed as {:?}", host, other),
+            }
+        }
+    }
+
+    #[test]
+    fn port_lists_give_each_host_its_port() {
+        assert_eq!(apply_port_list("a,b", &[6432]).unwrap(), ("a,b".to_string(), 6432));
+        assert_eq!(apply_port_list("a, ::1", &[5432, 5433]).unwrap(), ("a:5432,[::1]:5433".to_string(), 5432));
+        assert_eq!(apply_port_list("/tmp,b", &[5432, 5433]).unwrap(), ("/tmp,b:5433".to_string(), 5432));
+        assert!(matches!(apply_port_list("b,/tmp", &[5432, 5433]), Err(AppError::InvalidInput(_))));
+        assert!(matches!(apply_port_list("a", &[]), Err(AppError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn port_list_must_match_the_host_list() {
+        for (host, ports) in [("a,b,c", &[5432, 5433][..]), ("a", &[5432, 5433][..])] {
+            match apply_port_list(host, ports) {
+                Err(AppError::InvalidInput(message)) => assert!(message.contains("ports given for"), "{}", message),
+                other => panic!("{} with {:?} gave {:?}", host, ports, other),
+            }
+        }
+    }
+
+    #[test]
+    fn tunnels_need_a_single_tcp_host() {
+        assert_eq!(tunnel_endpoint("db:6432", 5432).unwrap(), ("db".to_string(), 6432));
+        assert!(tunnel_endpoint("a,b", 5432).is_err());
+        assert!(tunnel_endpoint("/tmp", 5432).is_err());
+    }
+}
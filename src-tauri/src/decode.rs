@@ -0,0 +1,844 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveTime};
+use serde::Serialize;
+use std::error::Error;
+use tokio_postgres::types::{FromSql, Kind, Type};
+use tokio_postgres::{Column, Row};
+
+type DecodeResult = Result<serde_json::Value, Box<dyn Error + Sync + Send>>;
+
+/// The raw binary value of a column, whatever its type.
+struct RawValue<'a>(&'a [u8]);
+
+impl<'a> FromSql<'a> for RawValue<'a> {
+    fn from_sql(_: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(RawValue(raw))
+    }
+
+    fn accepts(_: &Type) -> bool {
+        true
+    }
+}
+
+/// Column metadata from the statement's RowDescription.
+#[derive(Debug, Clone, Serialize)]
+pub struct ColumnInfo {
+    pub name: String,
+    /// Position of the column in each row array, starting at 0
+    pub ordinal: usize,
+    pub type_name: String,
+    pub type_oid: u32,
+    /// Source table and column number, when the column comes straight from a table
+    pub table_oid: Option<u32>,
+    pub attnum: Option<i16>,
+}
+
+impl ColumnInfo {
+    pub fn from_columns(columns: &[Column]) -> Vec<ColumnInfo> {
+        columns.iter().enumerate().map(|(ordinal, column)| ColumnInfo {
+            name: column.name().to_string(),
+            ordinal,
+            type_name: column.type_().name().to_string(),
+            type_oid: column.type_().oid(),
+            table_oid: column.table_oid(),
+            attnum: column.column_id(),
+        }).collect()
+    }
+}
+
+/// Convert a row to a JSON array in column order.
+pub fn row_to_array(row: &Row) -> serde_json::Value {
+    serde_json::Value::Array((0..row.columns().len()).map(|i| column_to_json(row, i)).collect())
+}
+
+/// Convert a row to a JSON object keyed by column name.
+pub fn row_to_json(row: &Row) -> serde_json::Value {
+    let mut row_map = serde_json::Map::new();
+    for (i, column) in row.columns().iter().enumerate() {
+        row_map.insert(column.name().to_string(), column_to_json(row, i));
+    }
+    serde_json::Value::Object(row_map)
+}
+
+/// Decode one column to JSON based on its type OID. Types we don't know the
+/// binary format of (mostly extension types) fall back to their text form.
+pub fn column_to_json(row: &Row, idx: usize) -> serde_json::Value {
+    let ty = row.columns()[idx].type_();
+    match row.try_get::<_, Option<RawValue>>(idx) {
+        Ok(Some(RawValue(raw))) => decode(ty, raw).unwrap_or_else(|_| fallback(raw)),
+        Ok(None) => serde_json::Value::Null,
+        Err(e) => serde_json::Value::String(format!("<{}>", e)),
+    }
+}
+
+/// Render a decoded value as plain text for CSV export; `None` for SQL NULL.
+pub fn json_to_text(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::Null => None,
+        serde_json::Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+fn decode(ty: &Type, raw: &[u8]) -> DecodeResult {
+    use serde_json::Value;
+
+    match ty.kind() {
+        Kind::Array(member) => return decode_array(member, raw),
+        Kind::Range(subtype) => return decode_range(subtype, raw).map(Value::String),
+        Kind::Multirange(subtype) => return decode_multirange(subtype, raw).map(Value::String),
+        Kind::Domain(base) => return decode(base, raw),
+        Kind::Composite(fields) => return decode_composite(fields, raw),
+        Kind::Enum(_) => return Ok(Value::String(std::str::from_utf8(raw)?.to_string())),
+        _ => {}
+    }
+
+    let value = match *ty {
+        Type::BOOL => Value::Bool(bool::from_sql(ty, raw)?),
+        Type::INT2 => Value::from(i16::from_sql(ty, raw)?),
+        Type::INT4 => Value::from(i32::from_sql(ty, raw)?),
+        Type::INT8 => Value::from(i64::from_sql(ty, raw)?),
+        Type::OID | Type::XID | Type::CID | Type::REGPROC | Type::REGPROCEDURE | Type::REGOPER
+        | Type::REGOPERATOR | Type::REGCLASS | Type::REGTYPE | Type::REGCONFIG | Type::REGDICTIONARY
+        | Type::REGNAMESPACE | Type::REGROLE | Type::REGCOLLATION => Value::from(read_u32(raw, 0)?),
+        Type::XID8 => Value::from(read_u64(raw, 0)?),
+        Type::CHAR => Value::String((i8::from_sql(ty, raw)? as u8 as char).to_string()),
+        Type::FLOAT4 => float_to_json(f32::from_sql(ty, raw)? as f64),
+        Type::FLOAT8 => float_to_json(f64::from_sql(ty, raw)?),
+        Type::NUMERIC => number_or_string(decode_numeric(raw)?),
+        Type::MONEY => Value::String(format_money(read_i64(raw, 0)?)),
+        Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME | Type::UNKNOWN | Type::XML
+        | Type::REFCURSOR => Value::String(std::str::from_utf8(raw)?.to_string()),
+        Type::JSON | Type::JSONB => serde_json::Value::from_sql(ty, raw)?,
+        Type::JSONPATH => Value::String(std::str::from_utf8(raw.get(1..).unwrap_or_default())?.to_string()),
+        Type::UUID => Value::String(uuid::Uuid::from_sql(ty, raw)?.to_string()),
+        Type::BYTEA => Value::String(format!("\\x{}", hex(raw))),
+        Type::DATE => Value::String(decode_date(raw)?),
+        Type::TIME => Value::String(format_time(read_i64(raw, 0)?)),
+        Type::TIMETZ => Value::String(decode_timetz(raw)?),
+        Type::TIMESTAMP => Value::String(decode_timestamp(raw, false)?),
+        Type::TIMESTAMPTZ => Value::String(decode_timestamp(raw, true)?),
+        Type::INTERVAL => Value::String(decode_interval(raw)?),
+        Type::INET | Type::CIDR => Value::String(decode_inet(raw, *ty == Type::CIDR)?),
+        Type::MACADDR | Type::MACADDR8 => Value::String(
+            raw.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(":")
+        ),
+        Type::BIT | Type::VARBIT => Value::String(decode_bits(raw)?),
+        Type::POINT => Value::String(format_point(raw, 0)?),
+        Type::LSEG => Value::String(format!("[{},{}]", format_point(raw, 0)?, format_point(raw, 16)?)),
+        Type::BOX => Value::String(format!("{},{}", format_point(raw, 0)?, format_point(raw, 16)?)),
+        Type::PATH => Value::String(decode_path(raw)?),
+        Type::POLYGON => Value::String(format!("({})", format_points(raw, 0)?)),
+        Type::LINE => Value::String(format!(
+            "{{{},{},{}}}",
+            read_f64(raw, 0)?, read_f64(raw, 8)?, read_f64(raw, 16)?
+        )),
+        Type::CIRCLE => Value::String(format!("<{},{}>", format_point(raw, 0)?, read_f64(raw, 16)?)),
+        Type::PG_LSN => {
+            let lsn = read_u64(raw, 0)?;
+            Value::String(format!("{:X}/{:X}", lsn >> 32, lsn & 0xFFFF_FFFF))
+        },
+        Type::TS_VECTOR => Value::String(decode_tsvector(raw)?),
+        // An anonymous row, like `SELECT (1, 'a')`; its fields are named the way row_to_json names them
+        Type::RECORD => decode_composite(&[], raw)?,
+        Type::RECORD_ARRAY => decode_array(&Type::RECORD, raw)?,
+        Type::VOID => Value::Null,
+        _ if ty.name() == "hstore" => decode_hstore(raw)?,
+        _ => fallback(raw),
+    };
+
+    Ok(value)
+}
+
+/// Extension types usually send their text form (sometimes behind a version
+/// byte); anything that isn't text is shown as hex like bytea.
+fn fallback(raw: &[u8]) -> serde_json::Value {
+    let text = match raw.first() {
+        Some(1) => &raw[1..],
+        _ => raw,
+    };
+    match std::str::from_utf8(text) {
+        Ok(s) if !s.chars().any(|c| c.is_control() && !c.is_whitespace()) => serde_json::Value::String(s.to_string()),
+        _ => serde_json::Value::String(format!("\\x{}", hex(raw))),
+    }
+}
+
+fn value_to_text(ty: &Type, raw: &[u8]) -> Result<String, Box<dyn Error + Sync + Send>> {
+    Ok(match decode(ty, raw)? {
+        serde_json::Value::String(s) => s,
+        other => other.to_string(),
+    })
+}
+
+fn decode_array(member: &Type, raw: &[u8]) -> DecodeResult {
+    let ndim = read_i32(raw, 0)?;
+    if ndim == 0 {
+        return Ok(serde_json::Value::Array(vec![]));
+    }
+    let ndim = usize::try_from(ndim).map_err(|_| "invalid array dimensions")?;
+
+    // Header: ndim, has-nulls flag, element oid, then (length, lower bound) per dimension
+    let mut dims = Vec::with_capacity(ndim.min(raw.len()));
+    for d in 0..ndim {
+        dims.push(usize::try_from(read_i32(raw, 12 + d * 8)?).map_err(|_| "invalid array dimensions")?);
+    }
+
+    let mut offset = 12 + ndim * 8;
+    let mut elements = Vec::new();
+    let total = dims.iter()
+        .try_fold(1usize, |total, &len| total.checked_mul(len))
+        .ok_or("invalid array dimensions")?;
+    for _ in 0..total {
+        let len = read_i32(raw, offset)?;
+        offset += 4;
+        if len < 0 {
+            elements.push(serde_json::Value::Null);
+        } else {
+            let bytes = slice(raw, offset, len as usize)?;
+            elements.push(decode(member, bytes).unwrap_or_else(|_| fallback(bytes)));
+            offset += len as usize;
+        }
+    }
+
+    Ok(nest(&dims, &mut elements.into_iter()))
+}
+
+// Rebuild multi-dimensional arrays from the flat, row-major element list
+fn nest(dims: &[usize], elements: &mut impl Iterator<Item = serde_json::Value>) -> serde_json::Value {
+    match dims.split_first() {
+        Some((&len, [])) => serde_json::Value::Array(elements.take(len).collect()),
+        Some((&len, rest)) => serde_json::Value::Array((0..len).map(|_| nest(rest, elements)).collect()),
+        None => serde_json::Value::Array(vec![]),
+    }
+}
+
+fn decode_range(subtype: &Type, raw: &[u8]) -> Result<String, Box<dyn Error + Sync + Send>> {
+    const EMPTY: u8 = 0x01;
+    const LB_INC: u8 = 0x02;
+    const UB_INC: u8 = 0x04;
+    const LB_INF: u8 = 0x08;
+    const UB_INF: u8 = 0x10;
+
+    let flags = *raw.first().ok_or("empty range value")?;
+    if flags & EMPTY != 0 {
+        return Ok("empty".to_string());
+    }
+
+    let mut offset = 1;
+    let mut bound = |infinite: bool| -> Result<String, Box<dyn Error + Sync + Send>> {
+        if infinite {
+            return Ok(String::new());
+        }
+        let len = read_i32(raw, offset)? as usize;
+        let text = value_to_text(subtype, slice(raw, offset + 4, len)?)?;
+        offset += 4 + len;
+        Ok(text)
+    };
+
+    let lower = bound(flags & LB_INF != 0)?;
+    let upper = bound(flags & UB_INF != 0)?;
+
+    Ok(format!(
+        "{}{},{}{}",
+        if flags & LB_INC != 0 { '[' } else { '(' },
+        lower,
+        upper,
+        if flags & UB_INC != 0 { ']' } else { ')' }
+    ))
+}
+
+fn decode_multirange(subtype: &Type, raw: &[u8]) -> Result<String, Box<dyn Error + Sync + Send>> {
+    let count = read_i32(raw, 0)? as usize;
+    let mut offset = 4;
+    let mut ranges = Vec::with_capacity(count.min(raw.len()));
+    for _ in 0..count {
+        let len = read_i32(raw, offset)? as usize;
+        ranges.push(decode_range(subtype, slice(raw, offset + 4, len)?)?);
+        offset += 4 + len;
+    }
+    Ok(format!("{{{}}}", ranges.join(",")))
+}
+
+fn decode_composite(fields: &[tokio_postgres::types::Field], raw: &[u8]) -> DecodeResult {
+    let count = read_i32(raw, 0)? as usize;
+    let mut offset = 4;
+    let mut object = serde_json::Map::new();
+    for i in 0..count {
+        // Each field: type oid, length (-1 for NULL), value
+        let oid = read_u32(raw, offset)?;
+        let len = read_i32(raw, offset + 4)?;
+        offset += 8;
+        let name = fields.get(i).map(|f| f.name().to_string()).unwrap_or_else(|| format!("f{}", i + 1));
+        let value = if len < 0 {
+            serde_json::Value::Null
+        } else {
+            let bytes = slice(raw, offset, len as usize)?;
+            offset += len as usize;
+            // Fields of a record only come with their oid, which is enough for built-in types
+            let ty = fields.get(i).map(|field| field.type_().clone()).or_else(|| Type::from_oid(oid));
+            match ty {
+                Some(ty) => decode(&ty, bytes).unwrap_or_else(|_| fallback(bytes)),
+                None => fallback(bytes),
+            }
+        };
+        object.insert(name, value);
+    }
+    Ok(serde_json::Value::Object(object))
+}
+
+fn decode_hstore(raw: &[u8]) -> DecodeResult {
+    let count = read_i32(raw, 0)? as usize;
+    let mut offset = 4;
+    let mut object = serde_json::Map::new();
+    for _ in 0..count {
+        let key_len = read_i32(raw, offset)? as usize;
+        let key = std::str::from_utf8(slice(raw, offset + 4, key_len)?)?.to_string();
+        offset += 4 + key_len;
+        let value_len = read_i32(raw, offset)?;
+        offset += 4;
+        let value = if value_len < 0 {
+            serde_json::Value::Null
+        } else {
+            let value = std::str::from_utf8(slice(raw, offset, value_len as usize)?)?.to_string();
+            offset += value_len as usize;
+            serde_json::Value::String(value)
+        };
+        object.insert(key, value);
+    }
+    Ok(serde_json::Value::Object(object))
+}
+
+/// NUMERIC in base-10000 digits, rendered exactly (including NaN and infinities).
+fn decode_numeric(raw: &[u8]) -> Result<String, Box<dyn Error + Sync + Send>> {
+    let ndigits = read_i16(raw, 0)? as usize;
+    let weight = read_i16(raw, 2)? as i32;
+    let sign = read_u16(raw, 4)?;
+    let dscale = read_u16(raw, 6)? as usize;
+
+    match sign {
+        0xC000 => return Ok("NaN".to_string()),
+        0xD000 => return Ok("Infinity".to_string()),
+        0xF000 => return Ok("-Infinity".to_string()),
+        _ => {}
+    }
+
+    let mut digits = Vec::with_capacity(ndigits.min(raw.len()));
+    for i in 0..ndigits {
+        digits.push(read_i16(raw, 8 + i * 2)?);
+    }
+
+    let mut result = String::new();
+    if sign == 0x4000 {
+        result.push('-');
+    }
+
+    // Integer part: digit groups with weight >= 0
+    if weight < 0 {
+        result.push('0');
+    } else {
+        for w in 0..=weight {
+            let digit = digits.get(w as usize).copied().unwrap_or(0);
+            if w == 0 {
+                result.push_str(&digit.to_string());
+            } else {
+                result.push_str(&format!("{:04}", digit));
+            }
+        }
+    }
+
+    if dscale > 0 {
+        let mut fraction = String::new();
+        let mut w = weight + 1;
+        while fraction.len() < dscale {
+            let digit = if w < 0 { 0 } else { digits.get(w as usize).copied().unwrap_or(0) };
+            fraction.push_str(&format!("{:04}", digit));
+            w += 1;
+        }
+        fraction.truncate(dscale);
+        result.push('.');
+        result.push_str(&fraction);
+    }
+
+    Ok(result)
+}
+
+/// Use a JSON number when it round-trips exactly, otherwise keep the text so
+/// large or precise numerics aren't silently rounded.
+fn number_or_string(text: String) -> serde_json::Value {
+    // Trailing zeros only carry the column's scale, not the value
+    let value = if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        text.as_str()
+    };
+
+    if let Ok(n) = value.parse::<i64>() {
+        return serde_json::Value::from(n);
+    }
+    match value.parse::<f64>() {
+        Ok(f) if f.is_finite() && f.to_string() == value => serde_json::Value::from(f),
+        _ => serde_json::Value::String(text),
+    }
+}
+
+fn float_to_json(value: f64) -> serde_json::Value {
+    match serde_json::Number::from_f64(value) {
+        Some(number) => serde_json::Value::Number(number),
+        None if value.is_nan() => serde_json::Value::String("NaN".to_string()),
+        None if value > 0.0 => serde_json::Value::String("Infinity".to_string()),
+        None => serde_json::Value::String("-Infinity".to_string()),
+    }
+}
+
+fn format_money(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    let abs = cents.unsigned_abs();
+    format!("{}{}.{:02}", sign, abs / 100, abs % 100)
+}
+
+// Days from 1970-01-01 to the Postgres epoch, 2000-01-01
+const PG_EPOCH_UNIX_DAYS: i64 = 10_957;
+const MICROS_PER_DAY: i64 = 86_400_000_000;
+
+fn pg_epoch() -> NaiveDate {
+    NaiveDate::from_ymd_opt(2000, 1, 1).unwrap()
+}
+
+fn decode_date(raw: &[u8]) -> Result<String, Box<dyn Error + Sync + Send>> {
+    let days = read_i32(raw, 0)?;
+    Ok(match days {
+        i32::MAX => "infinity".to_string(),
+        i32::MIN => "-infinity".to_string(),
+        _ => format_date(days.into(), ""),
+    })
+}
+
+// `days` since the Postgres epoch. Postgres has no year zero and writes BC dates with a suffix.
+fn format_date(days: i64, time: &str) -> String {
+    let (year, month, day) = match pg_epoch().checked_add_signed(Duration::days(days)) {
+        Some(date) => (i64::from(date.year()), date.month(), date.day()),
+        // Postgres dates go on to 5874897 AD, well past what chrono can hold
+        None => civil_from_days(days + PG_EPOCH_UNIX_DAYS),
+    };
+    if year > 0 {
+        format!("{:04}-{:02}-{:02}{}", year, month, day, time)
+    } else {
+        format!("{:04}-{:02}-{:02}{} BC", 1 - year, month, day, time)
+    }
+}
+
+// Year, month and day of the proleptic Gregorian calendar for days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // Months counted from March, so the leap day falls at the end of the year
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = (if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 }) as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn decode_timestamp(raw: &[u8], with_tz: bool) -> Result<String, Box<dyn Error + Sync + Send>> {
+    let micros = read_i64(raw, 0)?;
+    Ok(match micros {
+        i64::MAX => "infinity".to_string(),
+        i64::MIN => "-infinity".to_string(),
+        _ => {
+            // timestamptz arrives in UTC
+            let time = format!(
+                " {}{}",
+                format_time(micros.rem_euclid(MICROS_PER_DAY)),
+                if with_tz { "+00" } else { "" }
+            );
+            format_date(micros.div_euclid(MICROS_PER_DAY), &time)
+        }
+    })
+}
+
+fn format_time(micros: i64) -> String {
+    let secs = (micros / 1_000_000) as u32;
+    match NaiveTime::from_num_seconds_from_midnight_opt(secs, 0) {
+        Some(time) => format!("{}{}", time.format("%H:%M:%S"), format_fraction(micros % 1_000_000)),
+        // 24:00:00 is a valid time in Postgres but not in chrono
+        None => "24:00:00".to_string(),
+    }
+}
+
+// Fractional seconds the way Postgres prints them: omitted when zero, no trailing zeros
+fn format_fraction(micros: i64) -> String {
+    if micros == 0 {
+        String::new()
+    } else {
+        format!(".{:06}", micros).trim_end_matches('0').to_string()
+    }
+}
+
+fn decode_timetz(raw: &[u8]) -> Result<String, Box<dyn Error + Sync + Send>> {
+    let micros = read_i64(raw, 0)?;
+    // Stored as seconds west of UTC
+    let offset = -read_i32(raw, 8)?;
+    let sign = if offset < 0 { '-' } else { '+' };
+    let offset = offset.abs();
+    Ok(format!("{}{}{:02}:{:02}", format_time(micros), sign, offset / 3600, (offset % 3600) / 60))
+}
+
+fn decode_interval(raw: &[u8]) -> Result<String, Box<dyn Error + Sync + Send>> {
+    let micros = read_i64(raw, 0)?;
+    let days = read_i32(raw, 8)?;
+    let months = read_i32(raw, 12)?;
+
+    let mut parts = Vec::new();
+    let (years, months) = (months / 12, months % 12);
+    if years != 0 {
+        parts.push(format!("{} year{}", years, if years == 1 { "" } else { "s" }));
+    }
+    if months != 0 {
+        parts.push(format!("{} mon{}", months, if months == 1 { "" } else { "s" }));
+    }
+    if days != 0 {
+        parts.push(format!("{} day{}", days, if days == 1 { "" } else { "s" }));
+    }
+    if micros != 0 || parts.is_empty() {
+        let sign = if micros < 0 { "-" } else { "" };
+        let abs = micros.unsigned_abs();
+        let (hours, rest) = (abs / 3_600_000_000, abs % 3_600_000_000);
+        let (minutes, rest) = (rest / 60_000_000, rest % 60_000_000);
+        let (seconds, fraction) = (rest / 1_000_000, rest % 1_000_000);
+        let mut time = format!("{}{:02}:{:02}:{:02}", sign, hours, minutes, seconds);
+        time.push_str(&format_fraction(fraction as i64));
+        parts.push(time);
+    }
+
+    Ok(parts.join(" "))
+}
+
+fn decode_inet(raw: &[u8], is_cidr_type: bool) -> Result<String, Box<dyn Error + Sync + Send>> {
+    const PGSQL_AF_INET: u8 = 2;
+
+    let family = *raw.first().ok_or("empty inet value")?;
+    let bits = *raw.get(1).ok_or("truncated inet value")?;
+    let len = *raw.get(3).ok_or("truncated inet value")? as usize;
+    let addr = slice(raw, 4, len)?;
+
+    let (text, max_bits) = if family == PGSQL_AF_INET {
+        let octets: [u8; 4] = addr.try_into()?;
+        (std::net::Ipv4Addr::from(octets).to_string(), 32)
+    } else {
+        let octets: [u8; 16] = addr.try_into()?;
+        (std::net::Ipv6Addr::from(octets).to_string(), 128)
+    };
+
+    // inet omits the prefix for single hosts, cidr always shows it
+    if is_cidr_type || bits != max_bits {
+        Ok(format!("{}/{}", text, bits))
+    } else {
+        Ok(text)
+    }
+}
+
+fn decode_bits(raw: &[u8]) -> Result<String, Box<dyn Error + Sync + Send>> {
+    let len = read_i32(raw, 0)? as usize;
+    let bytes = slice(raw, 4, len.div_ceil(8))?;
+    Ok((0..len)
+        .map(|i| if bytes[i / 8] & (0x80 >> (i % 8)) != 0 { '1' } else { '0' })
+        .collect())
+}
+
+fn format_point(raw: &[u8], offset: usize) -> Result<String, Box<dyn Error + Sync + Send>> {
+    Ok(format!("({},{})", read_f64(raw, offset)?, read_f64(raw, offset + 8)?))
+}
+
+// A point count followed by that many points
+fn format_points(raw: &[u8], offset: usize) -> Result<String, Box<dyn Error + Sync + Send>> {
+    let count = read_i32(raw, offset)? as usize;
+    let points = (0..count)
+        .map(|i| format_point(raw, offset + 4 + i * 16))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(points.join(","))
+}
+
+fn decode_path(raw: &[u8]) -> Result<String, Box<dyn Error + Sync + Send>> {
+    let closed = *raw.first().ok_or("empty path value")? != 0;
+    let points = format_points(raw, 1)?;
+    Ok(if closed { format!("({})", points) } else { format!("[{}]", points) })
+}
+
+fn decode_tsvector(raw: &[u8]) -> Result<String, Box<dyn Error + Sync + Send>> {
+    let count = read_i32(raw, 0)? as usize;
+    let mut offset = 4;
+    // The count comes off the wire; each lexeme takes at least three bytes
+    let mut lexemes = Vec::with_capacity(count.min(raw.len()));
+    for _ in 0..count {
+        let end = raw[offset..].iter().position(|&b| b == 0).ok_or("unterminated lexeme")?;
+        let word = std::str::from_utf8(&raw[offset..offset + end])?;
+        offset += end + 1;
+
+        let npos = read_u16(raw, offset)? as usize;
+        offset += 2;
+        let mut positions = Vec::with_capacity(npos);
+        for _ in 0..npos {
+            let entry = read_u16(raw, offset)?;
+            offset += 2;
+            let weight = match entry >> 14 {
+                3 => "A",
+                2 => "B",
+                1 => "C",
+                _ => "",
+            };
+            positions.push(format!("{}{}", entry & 0x3FFF, weight));
+        }
+
+        let quoted = format!("'{}'", word.replace('\'', "''"));
+        if positions.is_empty() {
+            lexemes.push(quoted);
+        } else {
+            lexemes.push(format!("{}:{}", quoted, positions.join(",")));
+        }
+    }
+    Ok(lexemes.join(" "))
+}
+
+fn hex(raw: &[u8]) -> String {
+    raw.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn slice(raw: &[u8], offset: usize, len: usize) -> Result<&[u8], Box<dyn Error + Sync + Send>> {
+    raw.get(offset..offset + len).ok_or_else(|| "truncated value".into())
+}
+
+fn read_i16(raw: &[u8], offset: usize) -> Result<i16, Box<dyn Error + Sync + Send>> {
+    Ok(i16::from_be_bytes(slice(raw, offset, 2)?.try_into()?))
+}
+
+fn read_u16(raw: &[u8], offset: usize) -> Result<u16, Box<dyn Error + Sync + Send>> {
+    Ok(u16::from_be_bytes(slice(raw, offset, 2)?.try_into()?))
+}
+
+fn read_i32(raw: &[u8], offset: usize) -> Result<i32, Box<dyn Error + Sync + Send>> {
+    Ok(i32::from_be_bytes(slice(raw, offset, 4)?.try_into()?))
+}
+
+fn read_u32(raw: &[u8], offset: usize) -> Result<u32, Box<dyn Error + Sync + Send>> {
+    Ok(u32::from_be_bytes(slice(raw, offset, 4)?.try_into()?))
+}
+
+fn read_i64(raw: &[u8], offset: usize) -> Result<i64, Box<dyn Error + Sync + Send>> {
+    Ok(i64::from_be_bytes(slice(raw, offset, 8)?.try_into()?))
+}
+
+fn read_u64(raw: &[u8], offset: usize) -> Result<u64, Box<dyn Error + Sync + Send>> {
+    Ok(u64::from_be_bytes(slice(raw, offset, 8)?.try_into()?))
+}
+
+fn read_f64(raw: &[u8], offset: usize) -> Result<f64, Box<dyn Error + Sync + Send>> {
+    Ok(f64::from_be_bytes(slice(raw, offset, 8)?.try_into()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(days: i32) -> String {
+        decode_date(&days.to_be_bytes()).unwrap()
+    }
+
+    fn timestamp(micros: i64, with_tz: bool) -> String {
+        decode_timestamp(&micros.to_be_bytes(), with_tz).unwrap()
+    }
+
+    #[test]
+    fn dates_cover_the_postgres_range() {
+        assert_eq!(date(0), "2000-01-01");
+        assert_eq!(date(-1), "1999-12-31");
+        assert_eq!(date(2_921_940), "10000-01-01");
+        // The last day chrono can hold, and the first it can't
+        assert_eq!(date(95_015_644), "262143-12-31");
+        assert_eq!(date(95_015_645), "262144-01-01");
+        assert_eq!(date(2_145_031_948), "5874897-12-31");
+        assert_eq!(date(-2_451_179), "4713-11-24 BC");
+        assert_eq!(date(-730_119), "0001-01-01");
+        assert_eq!(date(-730_120), "0001-12-31 BC");
+    }
+
+    #[test]
+    fn infinite_dates_and_timestamps() {
+        assert_eq!(date(i32::MAX), "infinity");
+        assert_eq!(date(i32::MIN), "-infinity");
+        assert_eq!(timestamp(i64::MAX, false), "infinity");
+        assert_eq!(timestamp(i64::MIN, true), "-infinity");
+    }
+
+    #[test]
+    fn timestamps_cover_the_postgres_range() {
+        assert_eq!(timestamp(0, false), "2000-01-01 00:00:00");
+        assert_eq!(timestamp(-500_000, false), "1999-12-31 23:59:59.5");
+        assert_eq!(timestamp(1_500, true), "2000-01-01 00:00:00.0015+00");
+        assert_eq!(timestamp(9_223_371_331_199_999_999, false), "294276-12-31 23:59:59.999999");
+        assert_eq!(timestamp(-211_781_865_600_000_000, true), "4713-11-24 00:00:00+00 BC");
+    }
+
+    #[test]
+    fn truncated_values_are_errors() {
+        assert!(decode_date(&[0, 0]).is_err());
+        assert!(decode_timestamp(&[0; 4], false).is_err());
+    }
+
+    // Length-prefixed values as in arrays, records and ranges; None is NULL
+    fn with_lengths(values: &[Option<&[u8]>]) -> Vec<u8> {
+        let mut raw = Vec::new();
+        for value in values {
+            match value {
+                Some(value) => {
+                    raw.extend((value.len() as i32).to_be_bytes());
+                    raw.extend(*value);
+                },
+                None => raw.extend((-1i32).to_be_bytes()),
+            }
+        }
+        raw
+    }
+
+    fn numeric(digits: &[i16], weight: i16, sign: u16, dscale: u16) -> String {
+        let mut raw = Vec::new();
+        raw.extend((digits.len() as i16).to_be_bytes());
+        raw.extend(weight.to_be_bytes());
+        raw.extend(sign.to_be_bytes());
+        raw.extend(dscale.to_be_bytes());
+        for digit in digits {
+            raw.extend(digit.to_be_bytes());
+        }
+        decode_numeric(&raw).unwrap()
+    }
+
+    #[test]
+    fn numerics() {
+        assert_eq!(numeric(&[], 0, 0xC000, 0), "NaN");
+        assert_eq!(numeric(&[], 0, 0xD000, 0), "Infinity");
+        assert_eq!(numeric(&[], 0, 0, 2), "0.00");
+        assert_eq!(numeric(&[12, 5000], 0, 0x4000, 1), "-12.5");
+        // Fractions only, starting one and two base-10000 groups after the point
+        assert_eq!(numeric(&[1], -1, 0, 4), "0.0001");
+        assert_eq!(numeric(&[123], -2, 0, 8), "0.00000123");
+        // Trailing zero groups aren't sent
+        assert_eq!(numeric(&[1], 5, 0, 0), "100000000000000000000");
+        assert_eq!(numeric(&[1234, 5678, 9012, 3456, 7890], 4, 0, 0), "12345678901234567890");
+    }
+
+    #[test]
+    fn large_numerics_stay_exact() {
+        assert_eq!(number_or_string("12.50".to_string()), serde_json::json!(12.5));
+        assert_eq!(number_or_string("12345678901234567890".to_string()), serde_json::json!("12345678901234567890"));
+        assert_eq!(number_or_string("0.1000000000000000000001".to_string()), serde_json::json!("0.1000000000000000000001"));
+        assert_eq!(number_or_string("NaN".to_string()), serde_json::json!("NaN"));
+    }
+
+    #[test]
+    fn arrays() {
+        assert_eq!(decode(&Type::INT4_ARRAY, &[0; 12]).unwrap(), serde_json::json!([]));
+
+        // 2x2 with a NULL: ndim, has-nulls, element oid, then length and lower bound per dimension
+        let mut raw = Vec::new();
+        for header in [2, 1, Type::INT4.oid() as i32, 2, 1, 2, 1] {
+            raw.extend(header.to_be_bytes());
+        }
+        let (one, three, four) = (1i32.to_be_bytes(), 3i32.to_be_bytes(), 4i32.to_be_bytes());
+        raw.extend(with_lengths(&[Some(&one), None, Some(&three), Some(&four)]));
+        assert_eq!(decode(&Type::INT4_ARRAY, &raw).unwrap(), serde_json::json!([[1, null], [3, 4]]));
+
+        let mut raw = Vec::new();
+        for header in [1, 0, Type::TEXT.oid() as i32, 2, 1] {
+            raw.extend(header.to_be_bytes());
+        }
+        raw.extend(with_lengths(&[Some(b"a,b"), Some(b"")]));
+        assert_eq!(decode(&Type::TEXT_ARRAY, &raw).unwrap(), serde_json::json!(["a,b", ""]));
+    }
+
+    #[test]
+    fn inet_and_cidr() {
+        assert_eq!(decode_inet(&[2, 32, 0, 4, 192, 168, 0, 1], false).unwrap(), "192.168.0.1");
+        assert_eq!(decode_inet(&[2, 24, 0, 4, 192, 168, 0, 1], false).unwrap(), "192.168.0.1/24");
+        assert_eq!(decode_inet(&[2, 32, 1, 4, 10, 0, 0, 1], true).unwrap(), "10.0.0.1/32");
+
+        let mut v6 = vec![3, 128, 0, 16];
+        v6.extend(std::net::Ipv6Addr::LOCALHOST.octets());
+        assert_eq!(decode_inet(&v6, false).unwrap(), "::1");
+        v6[1] = 64;
+        assert_eq!(decode_inet(&v6, true).unwrap(), "::1/64");
+    }
+
+    #[test]
+    fn intervals() {
+        let interval = |micros: i64, days: i32, months: i32| {
+            let mut raw = micros.to_be_bytes().to_vec();
+            raw.extend(days.to_be_bytes());
+            raw.extend(months.to_be_bytes());
+            decode_interval(&raw).unwrap()
+        };
+        assert_eq!(interval(0, 0, 0), "00:00:00");
+        assert_eq!(interval(14_706_500_000, 3, 14), "1 year 2 mons 3 days 04:05:06.5");
+        assert_eq!(interval(-1_000_000, -1, -1), "-1 mons -1 days -00:00:01");
+        assert_eq!(interval(0, 1, 12), "1 year 1 day");
+        assert_eq!(interval(0, 0, -12), "-1 years");
+    }
+
+    #[test]
+    fn ranges() {
+        const EMPTY: u8 = 0x01;
+        const LB_INC: u8 = 0x02;
+        const UB_INC: u8 = 0x04;
+        const LB_INF: u8 = 0x08;
+        const UB_INF: u8 = 0x10;
+
+        let (one, ten) = (1i32.to_be_bytes(), 10i32.to_be_bytes());
+        let range = |flags: u8, bounds: &[&[u8]]| {
+            let mut raw = vec![flags];
+            raw.extend(with_lengths(&bounds.iter().map(|bound| Some(*bound)).collect::<Vec<_>>()));
+            decode_range(&Type::INT4, &raw).unwrap()
+        };
+        assert_eq!(range(EMPTY, &[]), "empty");
+        assert_eq!(range(LB_INC, &[&one, &ten]), "[1,10)");
+        assert_eq!(range(LB_INC | UB_INC, &[&one, &ten]), "[1,10]");
+        assert_eq!(range(LB_INF, &[&ten]), "(,10)");
+        assert_eq!(range(LB_INC | UB_INF, &[&one]), "[1,)");
+        assert_eq!(range(LB_INF | UB_INF, &[]), "(,)");
+    }
+
+    #[test]
+    fn tsvectors() {
+        let mut raw = 2i32.to_be_bytes().to_vec();
+        raw.extend(b"a\0");
+        raw.extend(0u16.to_be_bytes());
+        raw.extend(b"it's\0");
+        raw.extend(2u16.to_be_bytes());
+        raw.extend((1u16 | 3 << 14).to_be_bytes());
+        raw.extend(5u16.to_be_bytes());
+        assert_eq!(decode_tsvector(&raw).unwrap(), "'a' 'it''s':1A,5");
+    }
+
+    #[test]
+    fn records() {
+        let mut raw = 3i32.to_be_bytes().to_vec();
+        let fields: [(Type, Option<&[u8]>); 3] = [
+            (Type::INT4, Some(&42i32.to_be_bytes())),
+            (Type::TEXT, Some(b"hi")),
+            (Type::BOOL, None),
+        ];
+        for (ty, value) in fields {
+            raw.extend(ty.oid().to_be_bytes());
+            raw.extend(with_lengths(&[value]));
+        }
+        assert_eq!(decode(&Type::RECORD, &raw).unwrap(), serde_json::json!({"f1": 42, "f2": "hi", "f3": null}));
+    }
+
+    #[test]
+    fn bad_counts_are_errors() {
+        let negative = (-1i32).to_be_bytes();
+        assert!(decode_tsvector(&negative).is_err());
+        assert!(decode_multirange(&Type::INT4, &negative).is_err());
+        assert!(decode(&Type::INT4_ARRAY, &[negative, [0; 4], [0; 4]].concat()).is_err());
+        assert!(decode_numeric(&[0xff, 0xff, 0, 0, 0, 0, 0, 0]).is_err());
+    }
+}
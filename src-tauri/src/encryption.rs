@@ -1,163 +1,971 @@
 use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
 use ring::rand::{SecureRandom, SystemRandom};
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
-use std::sync::OnceLock;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri_plugin_store::{Store, StoreExt};
+
+use crate::connection;
+use crate::error::AppError;
 
-static MASTER_KEY: OnceLock<Vec<u8>> = OnceLock::new();
 const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+const ENCRYPTION_STORE: &str = "encryption.json";
+const CONNECTIONS_STORE: &str = "connections.json";
+const LOCKED_MESSAGE: &str = "Saved connections are locked, enter the master password to unlock them";
+
+// Encrypted values are this prefix followed by the base64 of the big-endian
+// version of the key that sealed them and nonce||ciphertext
+pub const ENCRYPTED_PREFIX: &str = "enc:v1:";
+const KEY_VERSION_LEN: usize = 4;
+// Values stored before the prefix: a format byte and key version header, or
+// a bare nonce||ciphertext sealed with key version 1 and no associated data
+const UNTAGGED_FORMAT: u8 = 1;
+const UNTAGGED_HEADER_LEN: usize = 5;
+const LEGACY_KEY_VERSION: u32 = 1;
+
+// Secret Service / Keychain / Credential Manager entry holding the master keys
+const KEYRING_SERVICE: &str = "com.bryanshort.queryowl";
+const KEYRING_USER: &str = "master-key";
+// Recorded in encryption.json once the keys have moved to the keyring
+const KEY_STORAGE_KEYRING: &str = "keyring";
+const KEY_STORAGE_FILE: &str = "file";
+const KEY_STORAGE_MASTER_PASSWORD: &str = "master_password";
+
+// Argon2id cost for deriving the key from the master password (RFC 9106, second recommended option)
+const ARGON2_MEMORY_KIB: u32 = 64 * 1024;
+const ARGON2_ITERATIONS: u32 = 3;
+const ARGON2_PARALLELISM: u32 = 4;
+const SALT_LEN: usize = 16;
+// Bounds for parameters read from files made elsewhere
+const ARGON2_MAX_MEMORY_KIB: u32 = 1024 * 1024;
+const ARGON2_MAX_ITERATIONS: u32 = 64;
+// Associated data of the master keys sealed with the password-derived key
+const WRAPPED_KEYS_AAD: &str = "queryowl/master-keys";
+// Check value of master passwords set before the keys were wrapped
+const VERIFIER_PLAINTEXT: &str = "queryowl-master-password";
+
+/// Secret fields of a stored connection, as JSON pointers. A ciphertext is
+/// bound to its connection id and field, so it can't be copied to another one.
+pub const PASSWORD_FIELD: &str = "/password";
+pub const SSH_PASSWORD_FIELD: &str = "/ssh_tunnel/password";
+pub const SSH_PASSPHRASE_FIELD: &str = "/ssh_tunnel/passphrase";
+const SECRET_FIELDS: [&str; 3] = [PASSWORD_FIELD, SSH_PASSWORD_FIELD, SSH_PASSPHRASE_FIELD];
+
+/// Master keys by version, base64 encoded. New secrets are sealed with
+/// `current`; older versions stay until nothing sealed with them is left.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct KeySet {
+    current: u32,
+    keys: BTreeMap<u32, String>,
+}
+
+impl KeySet {
+    fn single(version: u32, key: &[u8]) -> Self {
+        KeySet {
+            current: version,
+            keys: BTreeMap::from([(version, BASE64.encode(key))]),
+        }
+    }
+
+    fn generate() -> Result<Self, String> {
+        Ok(KeySet::single(LEGACY_KEY_VERSION, &generate_key()?))
+    }
+
+    /// A key set from the keyring: JSON, or the single base64 key stored before key versions.
+    fn parse(value: &str) -> Result<Self, String> {
+        if value.trim_start().starts_with('{') {
+            serde_json::from_str(value).map_err(|e| format!("Failed to parse master keys: {}", e))
+        } else {
+            let key = BASE64.decode(value.trim())
+                .map_err(|e| format!("Failed to decode key: {}", e))?;
+            Ok(KeySet::single(LEGACY_KEY_VERSION, &key))
+        }
+    }
+
+    fn key(&self, version: u32) -> Result<Vec<u8>, String> {
+        let key = self.keys.get(&version)
+            .ok_or_else(|| format!("Master key version {} is not available", version))?;
+        BASE64.decode(key).map_err(|e| format!("Failed to decode master key {}: {}", version, e))
+    }
+
+    /// This set plus a new random key, which becomes current.
+    fn rotated(&self) -> Result<Self, String> {
+        let version = self.keys.keys().max().copied().unwrap_or(0) + 1;
+        let mut rotated = self.clone();
+        rotated.keys.insert(version, BASE64.encode(generate_key()?));
+        rotated.current = version;
+        Ok(rotated)
+    }
+
+    fn current_only(&self) -> Self {
+        KeySet {
+            current: self.current,
+            keys: self.keys.iter()
+                .filter(|(version, _)| **version == self.current)
+                .map(|(version, key)| (*version, key.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// The keys in use. They are `None` while saved connections are locked behind
+/// the master password; `kek` is the key derived from it, used to store them.
+struct KeyState {
+    keys: Option<KeySet>,
+    kek: Option<Vec<u8>>,
+    master_password: bool,
+    auto_lock: Option<Duration>,
+    last_used: Instant,
+}
+
+static KEY_STATE: Mutex<Option<KeyState>> = Mutex::new(None);
+
+/// Argon2id salt and parameters for deriving a key from a password.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyDerivation {
+    salt: String,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+impl KeyDerivation {
+    /// The default parameters with a fresh salt.
+    fn generate() -> Result<Self, AppError> {
+        let mut salt = [0u8; SALT_LEN];
+        SystemRandom::new().fill(&mut salt)
+            .map_err(|_| AppError::Encryption("Failed to generate salt".to_string()))?;
+
+        Ok(KeyDerivation {
+            salt: BASE64.encode(salt),
+            memory_kib: ARGON2_MEMORY_KIB,
+            iterations: ARGON2_ITERATIONS,
+            parallelism: ARGON2_PARALLELISM,
+        })
+    }
 
+    fn derive_key(&self, password: &str) -> Result<Vec<u8>, AppError> {
+        let salt = BASE64.decode(&self.salt)
+            .map_err(|e| AppError::Encryption(format!("Failed to decode salt: {}", e)))?;
+        let params = argon2::Params::new(self.memory_kib, self.iterations, self.parallelism, Some(KEY_LEN))
+            .map_err(|e| AppError::Encryption(format!("Invalid key derivation parameters: {}", e)))?;
+        let argon2 = argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+
+        let mut key = vec![0u8; KEY_LEN];
+        argon2.hash_password_into(password.as_bytes(), &salt, &mut key)
+            .map_err(|e| AppError::Encryption(format!("Failed to derive key: {}", e)))?;
+        Ok(key)
+    }
+}
+
+/// Data sealed with a key derived from a passphrase instead of the master keys,
+/// so it can be opened on another machine.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PassphraseSealed {
+    #[serde(flatten)]
+    kdf: KeyDerivation,
+    data: String,
+}
+
+pub fn seal_with_passphrase(passphrase: &str, plaintext: &[u8], aad: &str) -> Result<PassphraseSealed, AppError> {
+    if passphrase.is_empty() {
+        return Err(AppError::InvalidInput("The passphrase cannot be empty".to_string()));
+    }
+    let kdf = KeyDerivation::generate()?;
+    let sealed = seal(&kdf.derive_key(passphrase)?, plaintext, aad.as_bytes()).map_err(AppError::Encryption)?;
+    Ok(PassphraseSealed { kdf, data: BASE64.encode(sealed) })
+}
+
+pub fn open_with_passphrase(sealed: &PassphraseSealed, passphrase: &str, aad: &str) -> Result<Vec<u8>, AppError> {
+    if sealed.kdf.memory_kib > ARGON2_MAX_MEMORY_KIB || sealed.kdf.iterations > ARGON2_MAX_ITERATIONS {
+        return Err(AppError::InvalidInput("Key derivation parameters are too large".to_string()));
+    }
+    let data = BASE64.decode(&sealed.data)
+        .map_err(|e| AppError::InvalidInput(format!("Failed to decode encrypted data: {}", e)))?;
+    open(&sealed.kdf.derive_key(passphrase)?, &data, aad.as_bytes())
+        .map_err(|_| AppError::Authentication("Wrong passphrase, or the file is damaged".to_string()))
+}
+
+/// Argon2id parameters for the master password and the master keys sealed with
+/// the key derived from it, kept in `encryption.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MasterPassword {
+    #[serde(flatten)]
+    kdf: KeyDerivation,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    wrapped_keys: Option<String>,
+    /// Only in settings from before the keys were wrapped, when the derived key sealed the secrets itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    verifier: Option<String>,
+}
+
+impl MasterPassword {
+    /// Parameters for a new master password, with a fresh salt, and the key derived from it.
+    fn create(password: &str) -> Result<(Self, Vec<u8>), AppError> {
+        if password.is_empty() {
+            return Err(AppError::InvalidInput("The master password cannot be empty".to_string()));
+        }
+        let master_password = MasterPassword {
+            kdf: KeyDerivation::generate()?,
+            wrapped_keys: None,
+            verifier: None,
+        };
+        let kek = master_password.kdf.derive_key(password)?;
+        Ok((master_password, kek))
+    }
+
+    fn wrap(&mut self, kek: &[u8], keys: &KeySet) -> Result<(), String> {
+        let json = serde_json::to_string(keys)
+            .map_err(|e| format!("Failed to serialize master keys: {}", e))?;
+        self.wrapped_keys = Some(BASE64.encode(seal(kek, json.as_bytes(), WRAPPED_KEYS_AAD.as_bytes())?));
+        self.verifier = None;
+        Ok(())
+    }
+
+    /// The derived key and the master keys, if `password` is the master password.
+    /// The flag is set for settings from before the keys were wrapped.
+    fn unlock(&self, password: &str) -> Result<(Vec<u8>, KeySet, bool), AppError> {
+        let kek = self.kdf.derive_key(password)?;
+        let wrong_password = || AppError::Authentication("Wrong master password".to_string());
+
+        if let Some(wrapped) = &self.wrapped_keys {
+            let sealed = BASE64.decode(wrapped)
+                .map_err(|e| AppError::Storage(format!("Failed to decode master keys: {}", e)))?;
+            let json = open(&kek, &sealed, WRAPPED_KEYS_AAD.as_bytes()).map_err(|_| wrong_password())?;
+            let keys = serde_json::from_slice(&json)
+                .map_err(|e| AppError::Storage(format!("Failed to parse master keys: {}", e)))?;
+            Ok((kek, keys, false))
+        } else if let Some(verifier) = &self.verifier {
+            let sealed = BASE64.decode(verifier)
+                .map_err(|e| AppError::Storage(format!("Failed to decode master password check: {}", e)))?;
+            match open(&kek, &sealed, &[]) {
+                Ok(check) if check == VERIFIER_PLAINTEXT.as_bytes() => {
+                    let keys = KeySet::single(LEGACY_KEY_VERSION, &kek);
+                    Ok((kek, keys, true))
+                },
+                _ => Err(wrong_password()),
+            }
+        } else {
+            Err(AppError::Storage("Master password settings hold no keys".to_string()))
+        }
+    }
+}
+
+/// What the frontend needs to know to offer unlocking and the master password settings.
+#[derive(Debug, Serialize)]
+pub struct EncryptionStatus {
+    pub master_password: bool,
+    pub locked: bool,
+    pub auto_lock_minutes: Option<u64>,
+}
+
+/// Load the master keys from the OS keyring, falling back to `encryption.json`
+/// when no keyring is available. Keys still kept in the file are moved into
+/// the keyring as soon as one can store them.
+///
+/// With a master password set the keys are sealed with it: saved connections
+/// stay locked until `unlock`.
 pub fn initialize_encryption(app_handle: &tauri::AppHandle) -> Result<(), String> {
-    use tauri_plugin_store::StoreExt;
-    
-    let store = app_handle.store_builder("encryption.json").build()
-        .map_err(|e| format!("Failed to build encryption store: {}", e))?;
-    
-    let key = if let Some(stored_key) = store.get("master_key") {
+    let store = encryption_store(app_handle)?;
+
+    let storage = store.get("key_storage")
+        .and_then(|value| value.as_str().map(str::to_string));
+    let auto_lock = store.get("auto_lock_minutes")
+        .and_then(|value| value.as_u64())
+        .map(|minutes| Duration::from_secs(minutes * 60));
+
+    if store.has("master_password") {
+        log::info!("Saved connections are locked until the master password is entered");
+        set_key_state(None, None, true, auto_lock);
+        return Ok(());
+    }
+
+    let file_keys = if let Some(stored_keys) = store.get("master_keys") {
+        Some(serde_json::from_value::<KeySet>(stored_keys)
+            .map_err(|e| format!("Failed to parse stored keys: {}", e))?)
+    } else if let Some(stored_key) = store.get("master_key") {
         let key_str: String = serde_json::from_value(stored_key.clone())
             .map_err(|e| format!("Failed to parse stored key: {}", e))?;
-        BASE64.decode(key_str)
-            .map_err(|e| format!("Failed to decode key: {}", e))?
+        let key = BASE64.decode(key_str)
+            .map_err(|e| format!("Failed to decode key: {}", e))?;
+        Some(KeySet::single(LEGACY_KEY_VERSION, &key))
+    } else {
+        None
+    };
+
+    let keys = if let Some(keys) = file_keys {
+        if save_keys(&store, &keys)? == KEY_STORAGE_KEYRING {
+            log::info!("Moved the master key from encryption.json into the OS keyring");
+        }
+        keys
     } else {
-        let rng = SystemRandom::new();
-        let mut key = vec![0u8; 32];
-        rng.fill(&mut key)
-            .map_err(|_| "Failed to generate random key")?;
-        
-        let key_str = BASE64.encode(&key);
-        store.set("master_key", serde_json::json!(key_str));
-        store.save().map_err(|e| format!("Failed to save encryption store: {}", e))?;
-        
-        key
+        let stored_in_keyring = storage.as_deref() == Some(KEY_STORAGE_KEYRING);
+        match read_keyring() {
+            Ok(Some(keys)) => keys,
+            // A new key would make every saved password unreadable, so don't
+            // replace a keyring key just because the keyring can't be reached now
+            Err(e) if stored_in_keyring => {
+                return Err(format!("{}. Saved passwords cannot be decrypted without it", e));
+            },
+            missing => {
+                if stored_in_keyring && matches!(missing, Ok(None)) {
+                    log::warn!("Master key is missing from the OS keyring, saved passwords will need to be entered again");
+                }
+                let keys = KeySet::generate()?;
+                save_keys(&store, &keys)?;
+                keys
+            },
+        }
     };
-    
-    MASTER_KEY.set(key).map_err(|_| "Failed to set master key")?;
+
+    set_key_state(Some(keys), None, false, auto_lock);
+    Ok(())
+}
+
+fn set_key_state(keys: Option<KeySet>, kek: Option<Vec<u8>>, master_password: bool, auto_lock: Option<Duration>) {
+    *KEY_STATE.lock().unwrap() = Some(KeyState {
+        keys,
+        kek,
+        master_password,
+        auto_lock,
+        last_used: Instant::now(),
+    });
+}
+
+fn update_key_state(update: impl FnOnce(&mut KeyState)) {
+    if let Some(state) = KEY_STATE.lock().unwrap().as_mut() {
+        update(state);
+        state.last_used = Instant::now();
+    }
+}
+
+fn encryption_store(app_handle: &tauri::AppHandle) -> Result<Arc<Store<tauri::Wry>>, String> {
+    app_handle.store_builder(ENCRYPTION_STORE).build()
+        .map_err(|e| format!("Failed to build encryption store: {}", e))
+}
+
+/// The keys in use; every use counts as activity for auto-lock.
+fn current_keys() -> Result<KeySet, String> {
+    let mut state = KEY_STATE.lock().unwrap();
+    let state = state.as_mut().ok_or("Encryption not initialized")?;
+    state.last_used = Instant::now();
+    state.keys.clone().ok_or_else(|| LOCKED_MESSAGE.to_string())
+}
+
+pub fn is_locked() -> bool {
+    KEY_STATE.lock().unwrap().as_ref().is_some_and(|state| state.keys.is_none())
+}
+
+pub fn status() -> EncryptionStatus {
+    let state = KEY_STATE.lock().unwrap();
+    EncryptionStatus {
+        master_password: state.as_ref().is_some_and(|state| state.master_password),
+        locked: state.as_ref().is_some_and(|state| state.keys.is_none()),
+        auto_lock_minutes: state.as_ref()
+            .and_then(|state| state.auto_lock)
+            .map(|auto_lock| auto_lock.as_secs() / 60),
+    }
+}
+
+fn stored_master_password(store: &Store<tauri::Wry>) -> Result<MasterPassword, AppError> {
+    let value = store.get("master_password")
+        .ok_or_else(|| AppError::InvalidInput("No master password is set".to_string()))?;
+    serde_json::from_value(value)
+        .map_err(|e| AppError::Storage(format!("Failed to parse master password settings: {}", e)))
+}
+
+/// Derive the key from the master password. Slow on purpose, so call it off the async runtime.
+pub fn unlock(app_handle: &tauri::AppHandle, password: &str) -> Result<(), AppError> {
+    let _store_lock = connection::lock_store();
+    let store = encryption_store(app_handle).map_err(AppError::Storage)?;
+    let (kek, keys, unwrapped) = stored_master_password(&store)?.unlock(password)?;
+    update_key_state(|state| {
+        state.keys = Some(keys);
+        state.kek = Some(kek);
+    });
+
+    // Passwords saved as plain text while locked can be encrypted now
+    migrate_connections(app_handle).map_err(AppError::Storage)?;
+    if unwrapped {
+        // Secrets were sealed with the password-derived key itself; move them to a random key
+        rotate_keys(app_handle)?;
+    }
+    Ok(())
+}
+
+/// Forget the keys. Live connections stay open; new ones need `unlock` first.
+pub fn lock() -> Result<(), AppError> {
+    let mut state = KEY_STATE.lock().unwrap();
+    match state.as_mut() {
+        Some(state) if state.master_password => {
+            state.keys = None;
+            state.kek = None;
+            Ok(())
+        },
+        _ => Err(AppError::InvalidInput("No master password is set".to_string())),
+    }
+}
+
+/// Lock when the saved credentials have not been used for the auto-lock time. Returns whether it locked.
+pub fn lock_if_idle() -> bool {
+    let mut state = KEY_STATE.lock().unwrap();
+    match state.as_mut() {
+        Some(state) if state.master_password && state.keys.is_some() => {
+            let idle = state.auto_lock.is_some_and(|auto_lock| state.last_used.elapsed() >= auto_lock);
+            if idle {
+                state.keys = None;
+                state.kek = None;
+            }
+            idle
+        },
+        _ => false,
+    }
+}
+
+pub fn set_auto_lock(app_handle: &tauri::AppHandle, minutes: Option<u64>) -> Result<(), AppError> {
+    let store = encryption_store(app_handle).map_err(AppError::Storage)?;
+    match minutes {
+        Some(minutes) if minutes > 0 => store.set("auto_lock_minutes", serde_json::json!(minutes)),
+        _ => {
+            store.delete("auto_lock_minutes");
+        },
+    }
+    store.save().map_err(|e| AppError::Storage(format!("Failed to save encryption store: {}", e)))?;
+
+    update_key_state(|state| {
+        state.auto_lock = minutes.filter(|minutes| *minutes > 0).map(|minutes| Duration::from_secs(minutes * 60));
+    });
     Ok(())
 }
 
-pub fn encrypt_password(password: &str) -> Result<String, String> {
-    let key_bytes = MASTER_KEY.get()
-        .ok_or("Encryption not initialized")?;
-    
+/// Protect saved connections with a master password, or change it. `current` is
+/// required when one is already set. Every stored secret is then re-encrypted
+/// with a new key, so copies of the old keys are of no use.
+pub fn set_master_password(app_handle: &tauri::AppHandle, current: Option<&str>, new: &str) -> Result<(), AppError> {
+    let _store_lock = connection::lock_store();
+    let store = encryption_store(app_handle).map_err(AppError::Storage)?;
+    let keys = if store.has("master_password") {
+        let current = current
+            .ok_or_else(|| AppError::InvalidInput("Enter the current master password".to_string()))?;
+        stored_master_password(&store)?.unlock(current)?.1
+    } else {
+        current_keys().map_err(AppError::Encryption)?
+    };
+    let key_in_keyring = store.get("key_storage") == Some(serde_json::json!(KEY_STORAGE_KEYRING));
+
+    let (mut master_password, kek) = MasterPassword::create(new)?;
+    master_password.wrap(&kek, &keys).map_err(AppError::Encryption)?;
+    store.set("master_password", serde_json::to_value(&master_password)
+        .map_err(|e| AppError::Storage(format!("Failed to serialize master password settings: {}", e)))?);
+    store.set("key_storage", serde_json::json!(KEY_STORAGE_MASTER_PASSWORD));
+    store.delete("master_key");
+    store.delete("master_keys");
+    store.save().map_err(|e| AppError::Storage(format!("Failed to save encryption store: {}", e)))?;
+
+    if key_in_keyring {
+        match keyring_entry().map(|entry| entry.delete_credential()) {
+            Ok(Ok(())) | Ok(Err(keyring::Error::NoEntry)) => {},
+            Ok(Err(e)) => log::warn!("Failed to remove the old master key from the OS keyring: {}", e),
+            Err(e) => log::warn!("{}", e),
+        }
+    }
+
+    update_key_state(|state| {
+        state.keys = Some(keys);
+        state.kek = Some(kek);
+        state.master_password = true;
+    });
+    rotate_keys(app_handle)
+}
+
+/// Go back to master keys kept in the OS keyring (or `encryption.json`).
+pub fn remove_master_password(app_handle: &tauri::AppHandle, current: &str) -> Result<(), AppError> {
+    let _store_lock = connection::lock_store();
+    let store = encryption_store(app_handle).map_err(AppError::Storage)?;
+    let (_, keys, _) = stored_master_password(&store)?.unlock(current)?;
+
+    store.delete("master_password");
+    save_keys(&store, &keys).map_err(AppError::Storage)?;
+
+    update_key_state(|state| {
+        state.keys = Some(keys);
+        state.kek = None;
+        state.master_password = false;
+    });
+    rotate_keys(app_handle)
+}
+
+/// Replace the master key with a new random one and re-encrypt every stored
+/// secret with it. The old key is only dropped once `connections.json` has
+/// been written, so an interruption leaves every secret readable.
+pub fn rotate_master_key(app_handle: &tauri::AppHandle) -> Result<(), AppError> {
+    let _store_lock = connection::lock_store();
+    rotate_keys(app_handle)
+}
+
+// Callers hold `connection::lock_store`
+fn rotate_keys(app_handle: &tauri::AppHandle) -> Result<(), AppError> {
+    let (keys, kek) = {
+        let state = KEY_STATE.lock().unwrap();
+        let state = state.as_ref()
+            .ok_or_else(|| AppError::Encryption("Encryption not initialized".to_string()))?;
+        let keys = state.keys.clone().ok_or_else(|| AppError::Locked(LOCKED_MESSAGE.to_string()))?;
+        (keys, state.kek.clone())
+    };
+    let store = encryption_store(app_handle).map_err(AppError::Storage)?;
+
+    let rotated = keys.rotated().map_err(AppError::Encryption)?;
+    persist_keys(&store, &rotated, kek.as_deref())?;
+    update_key_state(|state| state.keys = Some(rotated.clone()));
+
+    reencrypt_connections(app_handle, &rotated)?;
+
+    let current = rotated.current_only();
+    persist_keys(&store, &current, kek.as_deref())?;
+    update_key_state(|state| state.keys = Some(current));
+    log::info!("Rotated the master key to version {}", rotated.current);
+    Ok(())
+}
+
+/// Store the keys where the current settings keep them: sealed with the master password key, or in the keyring.
+fn persist_keys(store: &Store<tauri::Wry>, keys: &KeySet, kek: Option<&[u8]>) -> Result<(), AppError> {
+    match kek {
+        Some(kek) => {
+            let mut master_password = stored_master_password(store)?;
+            master_password.wrap(kek, keys).map_err(AppError::Encryption)?;
+            store.set("master_password", serde_json::to_value(&master_password)
+                .map_err(|e| AppError::Storage(format!("Failed to serialize master password settings: {}", e)))?);
+            store.set("key_storage", serde_json::json!(KEY_STORAGE_MASTER_PASSWORD));
+            store.save().map_err(|e| AppError::Storage(format!("Failed to save encryption store: {}", e)))
+        },
+        None => save_keys(store, keys).map(|_| ()).map_err(AppError::Storage),
+    }
+}
+
+/// Re-encrypt every stored secret with the current key. Nothing is written
+/// unless every secret decrypts.
+fn reencrypt_connections(app_handle: &tauri::AppHandle, keys: &KeySet) -> Result<(), AppError> {
+    let store = app_handle.store_builder(CONNECTIONS_STORE).build()
+        .map_err(|e| AppError::Storage(format!("Failed to build store: {}", e)))?;
+    let Some(connections_value) = store.get("connections") else {
+        return Ok(());
+    };
+    let mut connections: Vec<serde_json::Value> = serde_json::from_value(connections_value)
+        .map_err(|e| AppError::Storage(format!("Failed to parse connections: {}", e)))?;
+
+    for conn in &mut connections {
+        let id = conn.get("id").and_then(|id| id.as_str()).unwrap_or_default().to_string();
+        for field in SECRET_FIELDS {
+            if let Some(serde_json::Value::String(secret)) = conn.pointer_mut(field) {
+                if is_encrypted(secret) {
+                    let plain = open_secret(keys, secret, &id, field)
+                        .map_err(|e| AppError::Encryption(format!("Failed to re-encrypt saved connections: {}", e)))?;
+                    *secret = seal_secret(keys, &plain, &id, field).map_err(AppError::Encryption)?;
+                }
+            }
+        }
+    }
+
+    store.set("connections", serde_json::json!(connections));
+    store.save().map_err(|e| AppError::Storage(format!("Failed to save re-encrypted connections: {}", e)))
+}
+
+fn generate_key() -> Result<Vec<u8>, String> {
+    let rng = SystemRandom::new();
+    let mut key = vec![0u8; KEY_LEN];
+    rng.fill(&mut key)
+        .map_err(|_| "Failed to generate random key")?;
+    Ok(key)
+}
+
+/// Store the keys in the keyring, or in `encryption.json` without one. Returns where they went.
+fn save_keys(store: &Store<tauri::Wry>, keys: &KeySet) -> Result<&'static str, String> {
+    let storage = match write_keyring(keys) {
+        Ok(()) => {
+            store.delete("master_keys");
+            KEY_STORAGE_KEYRING
+        },
+        Err(e) => {
+            log::warn!("OS keyring unavailable, keeping the master key in encryption.json: {}", e);
+            store.set("master_keys", serde_json::to_value(keys)
+                .map_err(|e| format!("Failed to serialize master keys: {}", e))?);
+            KEY_STORAGE_FILE
+        },
+    };
+    store.delete("master_key");
+    store.set("key_storage", serde_json::json!(storage));
+    store.save().map_err(|e| format!("Failed to save encryption store: {}", e))?;
+    Ok(storage)
+}
+
+fn keyring_entry() -> Result<keyring::Entry, String> {
+    keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)
+        .map_err(|e| format!("Failed to open OS keyring: {}", e))
+}
+
+fn read_keyring() -> Result<Option<KeySet>, String> {
+    match keyring_entry()?.get_password() {
+        Ok(value) => KeySet::parse(&value).map(Some),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(format!("Failed to read master key from OS keyring: {}", e)),
+    }
+}
+
+/// Store the keys and read them back, so the file copy is only dropped once the keyring really has them.
+fn write_keyring(keys: &KeySet) -> Result<(), String> {
+    let json = serde_json::to_string(keys)
+        .map_err(|e| format!("Failed to serialize master keys: {}", e))?;
+    keyring_entry()?.set_password(&json)
+        .map_err(|e| format!("Failed to write master key to OS keyring: {}", e))?;
+    match read_keyring()? {
+        Some(stored) if stored == *keys => Ok(()),
+        _ => Err("Master key read back from OS keyring does not match".to_string()),
+    }
+}
+
+/// Encrypt a connection's secret with the current key, bound to the connection id and field.
+pub fn encrypt_password(password: &str, connection_id: &str, field: &str) -> Result<String, String> {
+    seal_secret(&current_keys()?, password, connection_id, field)
+}
+
+fn seal_secret(keys: &KeySet, password: &str, connection_id: &str, field: &str) -> Result<String, String> {
+    let key = keys.key(keys.current)?;
+    let sealed = seal(&key, password.as_bytes(), secret_aad(connection_id, field).as_bytes())?;
+
+    let mut payload = Vec::with_capacity(KEY_VERSION_LEN + sealed.len());
+    payload.extend_from_slice(&keys.current.to_be_bytes());
+    payload.extend_from_slice(&sealed);
+    Ok(format!("{}{}", ENCRYPTED_PREFIX, BASE64.encode(payload)))
+}
+
+fn secret_aad(connection_id: &str, field: &str) -> String {
+    format!("{}{}", connection_id, field)
+}
+
+/// nonce || ciphertext+tag
+fn seal(key_bytes: &[u8], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, String> {
     let unbound_key = UnboundKey::new(&AES_256_GCM, key_bytes)
         .map_err(|_| "Failed to create encryption key")?;
     let key = LessSafeKey::new(unbound_key);
-    
+
     // Generate a random nonce
     let rng = SystemRandom::new();
     let mut nonce_bytes = [0u8; NONCE_LEN];
     rng.fill(&mut nonce_bytes)
         .map_err(|_| "Failed to generate nonce")?;
-    
+
     let nonce = Nonce::try_assume_unique_for_key(&nonce_bytes)
         .map_err(|_| "Failed to create nonce")?;
-    
-    // Encrypt the password
-    let mut in_out = password.as_bytes().to_vec();
-    
-    key.seal_in_place_append_tag(nonce, Aad::empty(), &mut in_out)
+
+    let mut in_out = plaintext.to_vec();
+
+    key.seal_in_place_append_tag(nonce, Aad::from(aad), &mut in_out)
         .map_err(|_| "Failed to encrypt password")?;
-    
+
     // Combine nonce and ciphertext+tag
     let mut result = Vec::with_capacity(nonce_bytes.len() + in_out.len());
     result.extend_from_slice(&nonce_bytes);
     result.extend_from_slice(&in_out);
-    
-    Ok(BASE64.encode(result))
+    Ok(result)
 }
 
-pub fn decrypt_password(encrypted: &str) -> Result<String, String> {
-    // First check if this is actually an encrypted password
+/// Decrypt a connection's secret. Anything other than an encrypted value
+/// sealed for this connection and field is an error, never the password itself.
+pub fn decrypt_password(encrypted: &str, connection_id: &str, field: &str) -> Result<String, String> {
     if encrypted.is_empty() {
         return Ok(String::new());
     }
-    
-    // Try to decode from base64
-    let encrypted_bytes = match BASE64.decode(encrypted) {
-        Ok(bytes) => bytes,
-        Err(_) => {
-            // Not base64, might be plain text - return as is
-            println!("Warning: Password doesn't appear to be base64 encoded, returning as-is");
-            return Ok(encrypted.to_string());
+    open_secret(&current_keys()?, encrypted, connection_id, field)
+}
+
+fn open_secret(keys: &KeySet, encrypted: &str, connection_id: &str, field: &str) -> Result<String, String> {
+    let payload = encrypted.strip_prefix(ENCRYPTED_PREFIX)
+        .ok_or("Saved password is not encrypted, edit the connection and enter it again")?;
+    let encrypted_bytes = BASE64.decode(payload)
+        .map_err(|e| format!("Encrypted password is corrupted: {}", e))?;
+    if encrypted_bytes.len() < KEY_VERSION_LEN + NONCE_LEN + TAG_LEN {
+        return Err("Encrypted password is corrupted: too short".to_string());
+    }
+
+    let (version, sealed) = encrypted_bytes.split_at(KEY_VERSION_LEN);
+    let key = keys.key(u32::from_be_bytes([version[0], version[1], version[2], version[3]]))?;
+    let plaintext = open(&key, sealed, secret_aad(connection_id, field).as_bytes())?;
+    String::from_utf8(plaintext)
+        .map_err(|e| format!("Failed to convert decrypted bytes to string: {}", e))
+}
+
+/// Decrypt a value stored before the prefix, in either of its formats.
+fn open_untagged(keys: &KeySet, encrypted: &str, connection_id: &str, field: &str) -> Result<String, String> {
+    let encrypted_bytes = BASE64.decode(encrypted)
+        .map_err(|e| format!("Failed to decode encrypted password: {}", e))?;
+
+    let versioned = encrypted_bytes.len() >= UNTAGGED_HEADER_LEN + NONCE_LEN + TAG_LEN
+        && encrypted_bytes[0] == UNTAGGED_FORMAT;
+    if versioned {
+        let version = u32::from_be_bytes([encrypted_bytes[1], encrypted_bytes[2], encrypted_bytes[3], encrypted_bytes[4]]);
+        if let Ok(key) = keys.key(version) {
+            let aad = secret_aad(connection_id, field);
+            if let Ok(plaintext) = open(&key, &encrypted_bytes[UNTAGGED_HEADER_LEN..], aad.as_bytes()) {
+                return String::from_utf8(plaintext)
+                    .map_err(|e| format!("Failed to convert decrypted bytes to string: {}", e));
+            }
         }
-    };
-    
-    // Check minimum length (12 byte nonce + 1 byte ciphertext + 16 byte tag = 29 bytes)
-    if encrypted_bytes.len() < 29 {
-        println!("Warning: Encrypted password too short ({}), returning as-is", encrypted_bytes.len());
-        return Ok(encrypted.to_string());
-    }
-    
-    let key_bytes = MASTER_KEY.get()
-        .ok_or("Encryption not initialized")?;
-    
+    }
+
+    // A bare nonce can start with the format byte too, so fall back to the oldest format
+    let plaintext = open(&keys.key(LEGACY_KEY_VERSION)?, &encrypted_bytes, &[])?;
+    String::from_utf8(plaintext)
+        .map_err(|e| format!("Failed to convert decrypted bytes to string: {}", e))
+}
+
+fn open(key_bytes: &[u8], sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>, String> {
+    if sealed.len() < NONCE_LEN + TAG_LEN {
+        return Err("Encrypted password is too short".to_string());
+    }
+
     let unbound_key = UnboundKey::new(&AES_256_GCM, key_bytes)
         .map_err(|_| "Failed to create decryption key")?;
     let key = LessSafeKey::new(unbound_key);
-    
+
     // Split nonce and ciphertext+tag
-    let (nonce_bytes, ciphertext) = encrypted_bytes.split_at(NONCE_LEN);
-    
+    let (nonce_bytes, ciphertext) = sealed.split_at(NONCE_LEN);
+
     let nonce = Nonce::try_assume_unique_for_key(nonce_bytes)
         .map_err(|_| "Failed to create nonce from bytes")?;
-    
+
     let mut in_out = ciphertext.to_vec();
-    
+
     // Decrypt
-    let plaintext = key.open_in_place(nonce, Aad::empty(), &mut in_out)
+    let plaintext = key.open_in_place(nonce, Aad::from(aad), &mut in_out)
         .map_err(|e| format!("Failed to decrypt - password may be corrupted: {:?}", e))?;
-    
-    String::from_utf8(plaintext.to_vec())
-        .map_err(|e| format!("Failed to convert decrypted bytes to string: {}", e))
+    Ok(plaintext.to_vec())
 }
 
 pub fn is_encrypted(value: &str) -> bool {
-    if value.is_empty() {
-        return false;
-    }
-    
-    // Check if it's valid base64 and has the right length
-    if let Ok(decoded) = BASE64.decode(value) {
-        // Must have at least 12 byte nonce + 1 byte ciphertext + 16 byte auth tag = 29 bytes
-        decoded.len() >= 29
-    } else {
-        false
-    }
+    value.starts_with(ENCRYPTED_PREFIX)
 }
 
+/// Tag every stored secret: values from before the prefix are decrypted and
+/// plain text is taken as is, then sealed with the current key, bound to
+/// their connection. A value that doesn't decrypt is plain text, even when it
+/// looks like base64.
 pub fn migrate_existing_connections(app_handle: &tauri::AppHandle) -> Result<(), String> {
-    use tauri_plugin_store::StoreExt;
-    
-    let store = app_handle.store_builder("connections.json").build()
+    let _store_lock = connection::lock_store();
+    migrate_connections(app_handle)
+}
+
+// An untagged secret sealed with the current key
+fn migrate_secret(keys: &KeySet, secret: &str, connection_id: &str, field: &str) -> Result<String, String> {
+    let plain = match open_untagged(keys, secret, connection_id, field) {
+        Ok(plain) => plain,
+        Err(_) => {
+            log::info!("Migrating unencrypted password for connection");
+            secret.to_string()
+        },
+    };
+    seal_secret(keys, &plain, connection_id, field)
+}
+
+// Callers hold `connection::lock_store`
+fn migrate_connections(app_handle: &tauri::AppHandle) -> Result<(), String> {
+    // Runs again on unlock
+    if is_locked() {
+        return Ok(());
+    }
+    let keys = current_keys()?;
+
+    let store = app_handle.store_builder(CONNECTIONS_STORE).build()
         .map_err(|e| format!("Failed to build store: {}", e))?;
-    
+
     if let Some(connections_value) = store.get("connections") {
         let mut connections: Vec<serde_json::Value> = serde_json::from_value(connections_value.clone())
             .unwrap_or_default();
-        
-        let mut updated = false;
+
+        let mut migrated = 0;
         for conn in &mut connections {
-            if let Some(password) = conn.get("password").and_then(|p| p.as_str()) {
-                if !password.is_empty() && !is_encrypted(password) {
-                    println!("Migrating unencrypted password for connection");
-                    match encrypt_password(password) {
-                        Ok(encrypted) => {
-                            conn["password"] = serde_json::json!(encrypted);
-                            updated = true;
-                        },
-                        Err(e) => {
-                            println!("Warning: Failed to migrate password: {}", e);
-                        }
+            let id = conn.get("id").and_then(|id| id.as_str()).unwrap_or_default().to_string();
+            for field in SECRET_FIELDS {
+                let Some(serde_json::Value::String(secret)) = conn.pointer_mut(field) else {
+                    continue;
+                };
+                if secret.is_empty() || is_encrypted(secret) {
+                    continue;
+                }
+
+                match migrate_secret(&keys, secret, &id, field) {
+                    Ok(encrypted) => {
+                        *secret = encrypted;
+                        migrated += 1;
+                    },
+                    Err(e) => {
+                        log::warn!("Failed to migrate password: {}", e);
                     }
                 }
             }
         }
-        
-        if updated {
+
+        if migrated > 0 {
             store.set("connections", serde_json::json!(connections));
             store.save().map_err(|e| format!("Failed to save migrated connections: {}", e))?;
-            println!("Migrated {} connections to encrypted passwords", connections.len());
+            log::info!("Migrated {} stored secrets to the current encryption format", migrated);
         }
     }
-    
+
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> KeySet {
+        KeySet::generate().unwrap()
+    }
+
+    // Cheap parameters, the defaults take seconds in debug builds
+    fn test_master_password() -> MasterPassword {
+        MasterPassword {
+            kdf: KeyDerivation {
+                salt: BASE64.encode([7u8; SALT_LEN]),
+                memory_kib: 64,
+                iterations: 1,
+                parallelism: 1,
+            },
+            wrapped_keys: None,
+            verifier: None,
+        }
+    }
+
+    #[test]
+    fn sealed_secrets_round_trip() {
+        let keys = keys();
+        let sealed = seal_secret(&keys, "s3cret", "conn-1", PASSWORD_FIELD).unwrap();
+        assert!(sealed.starts_with(ENCRYPTED_PREFIX));
+        assert!(!sealed.contains("s3cret"));
+        assert_eq!(open_secret(&keys, &sealed, "conn-1", PASSWORD_FIELD).unwrap(), "s3cret");
+        assert_ne!(sealed, seal_secret(&keys, "s3cret", "conn-1", PASSWORD_FIELD).unwrap());
+    }
+
+    #[test]
+    fn secrets_are_bound_to_their_connection_and_field() {
+        let keys = keys();
+        let sealed = seal_secret(&keys, "s3cret", "conn-1", PASSWORD_FIELD).unwrap();
+        assert!(open_secret(&keys, &sealed, "conn-2", PASSWORD_FIELD).is_err());
+        assert!(open_secret(&keys, &sealed, "conn-1", SSH_PASSWORD_FIELD).is_err());
+    }
+
+    #[test]
+    fn parses_key_sets_and_the_single_key_from_before_versions() {
+        let keys = keys();
+        assert_eq!(KeySet::parse(&serde_json::to_string(&keys).unwrap()).unwrap(), keys);
+
+        let legacy = KeySet::parse(&BASE64.encode([9u8; KEY_LEN])).unwrap();
+        assert_eq!(legacy.current, LEGACY_KEY_VERSION);
+        assert_eq!(legacy.key(LEGACY_KEY_VERSION).unwrap(), vec![9u8; KEY_LEN]);
+
+        assert!(KeySet::parse("{not json").is_err());
+        assert!(KeySet::parse("not base64!").is_err());
+    }
+
+    #[test]
+    fn rotation_seals_with_the_new_key_and_still_opens_old_secrets() {
+        let keys = keys();
+        let old = seal_secret(&keys, "old", "conn-1", PASSWORD_FIELD).unwrap();
+
+        let rotated = keys.rotated().unwrap();
+        assert_eq!(rotated.current, keys.current + 1);
+        assert_eq!(open_secret(&rotated, &old, "conn-1", PASSWORD_FIELD).unwrap(), "old");
+
+        let new = seal_secret(&rotated, "new", "conn-1", PASSWORD_FIELD).unwrap();
+        let payload = BASE64.decode(new.strip_prefix(ENCRYPTED_PREFIX).unwrap()).unwrap();
+        assert_eq!(payload[..KEY_VERSION_LEN], rotated.current.to_be_bytes());
+
+        let current = rotated.current_only();
+        assert_eq!(current.keys.len(), 1);
+        assert_eq!(open_secret(&current, &new, "conn-1", PASSWORD_FIELD).unwrap(), "new");
+        assert!(open_secret(&current, &old, "conn-1", PASSWORD_FIELD).is_err());
+    }
+
+    #[test]
+    fn master_password_unlocks_the_wrapped_keys() {
+        let keys = keys();
+        let mut master_password = test_master_password();
+        let kek = master_password.kdf.derive_key("correct horse").unwrap();
+        master_password.wrap(&kek, &keys).unwrap();
+
+        let (unlocked_kek, unlocked, unwrapped) = master_password.unlock("correct horse").unwrap();
+        assert_eq!(unlocked_kek, kek);
+        assert_eq!(unlocked, keys);
+        assert!(!unwrapped);
+
+        assert!(matches!(master_password.unlock("wrong horse"), Err(AppError::Authentication(_))));
+    }
+
+    #[test]
+    fn master_password_from_before_wrapped_keys() {
+        let mut master_password = test_master_password();
+        let kek = master_password.kdf.derive_key("correct horse").unwrap();
+        master_password.verifier = Some(BASE64.encode(seal(&kek, VERIFIER_PLAINTEXT.as_bytes(), &[]).unwrap()));
+
+        let (_, keys, unwrapped) = master_password.unlock("correct horse").unwrap();
+        assert!(unwrapped);
+        assert_eq!(keys.key(LEGACY_KEY_VERSION).unwrap(), kek);
+        assert!(matches!(master_password.unlock("wrong horse"), Err(AppError::Authentication(_))));
+    }
+
+    #[test]
+    fn opens_both_untagged_formats() {
+        let keys = keys().rotated().unwrap();
+
+        // A bare nonce||ciphertext under key version 1 and no associated data
+        let bare = BASE64.encode(seal(&keys.key(LEGACY_KEY_VERSION).unwrap(), b"bare", &[]).unwrap());
+        assert_eq!(open_untagged(&keys, &bare, "conn-1", PASSWORD_FIELD).unwrap(), "bare");
+
+        // A format byte and key version, bound to the connection
+        let mut versioned = vec![UNTAGGED_FORMAT];
+        versioned.extend_from_slice(&keys.current.to_be_bytes());
+        let aad = secret_aad("conn-1", PASSWORD_FIELD);
+        versioned.extend(seal(&keys.key(keys.current).unwrap(), b"versioned", aad.as_bytes()).unwrap());
+        let versioned = BASE64.encode(versioned);
+        assert_eq!(open_untagged(&keys, &versioned, "conn-1", PASSWORD_FIELD).unwrap(), "versioned");
+        assert!(open_untagged(&keys, &versioned, "conn-2", PASSWORD_FIELD).is_err());
+    }
+
+    #[test]
+    fn migrates_plain_text_that_looks_like_base64() {
+        let keys = keys();
+        let password = BASE64.encode([42u8; 32]);
+        let migrated = migrate_secret(&keys, &password, "conn-1", PASSWORD_FIELD).unwrap();
+        assert_eq!(open_secret(&keys, &migrated, "conn-1", PASSWORD_FIELD).unwrap(), password);
+
+        let legacy = BASE64.encode(seal(&keys.key(LEGACY_KEY_VERSION).unwrap(), b"s3cret", &[]).unwrap());
+        let migrated = migrate_secret(&keys, &legacy, "conn-1", PASSWORD_FIELD).unwrap();
+        assert_eq!(open_secret(&keys, &migrated, "conn-1", PASSWORD_FIELD).unwrap(), "s3cret");
+    }
+
+    #[test]
+    fn strict_decryption_rejects_anything_else() {
+        let keys = keys();
+        let sealed = seal_secret(&keys, "s3cret", "conn-1", PASSWORD_FIELD).unwrap();
+
+        // Untagged values are never passed through as the password
+        assert!(open_secret(&keys, "s3cret", "conn-1", PASSWORD_FIELD).is_err());
+        assert!(open_secret(&keys, &format!("{}not base64!", ENCRYPTED_PREFIX), "conn-1", PASSWORD_FIELD).is_err());
+        assert!(open_secret(&keys, &format!("{}{}", ENCRYPTED_PREFIX, BASE64.encode([0u8; 8])), "conn-1", PASSWORD_FIELD).is_err());
+
+        let mut payload = BASE64.decode(sealed.strip_prefix(ENCRYPTED_PREFIX).unwrap()).unwrap();
+        let last = payload.len() - 1;
+        payload[last] ^= 1;
+        let tampered = format!("{}{}", ENCRYPTED_PREFIX, BASE64.encode(&payload));
+        assert!(open_secret(&keys, &tampered, "conn-1", PASSWORD_FIELD).is_err());
+
+        payload[last] ^= 1;
+        payload[..KEY_VERSION_LEN].copy_from_slice(&99u32.to_be_bytes());
+        let unknown_key = format!("{}{}", ENCRYPTED_PREFIX, BASE64.encode(&payload));
+        assert!(open_secret(&keys, &unknown_key, "conn-1", PASSWORD_FIELD).unwrap_err().contains("version 99"));
+    }
+}
@@ -0,0 +1,124 @@
+use serde::ser::{Error as _, SerializeMap};
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+use crate::query_error::QueryError;
+
+/// Errors returned by commands. They reach the frontend as `{ kind, message }`,
+/// with the server's error fields added for `query` errors.
+#[derive(Debug)]
+pub enum AppError {
+    NotFound(String),
+    InvalidInput(String),
+    /// Reading or writing the app's stores
+    Storage(String),
+    Encryption(String),
+    /// Saved connections are locked behind the master password
+    Locked(String),
+    Tls(String),
+    /// The server could not be reached, or the connection dropped
+    Connection(String),
+    /// The server rejected the credentials
+    Authentication(String),
+    /// The SSH tunnel to the server could not be opened
+    Tunnel(String),
+    Query(Box<QueryError>),
+    Transaction(String),
+    Io(String),
+}
+
+impl AppError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::InvalidInput(_) => "invalid_input",
+            AppError::Storage(_) => "storage",
+            AppError::Encryption(_) => "encryption",
+            AppError::Locked(_) => "locked",
+            AppError::Tls(_) => "tls",
+            AppError::Connection(_) => "connection",
+            AppError::Authentication(_) => "authentication",
+            AppError::Tunnel(_) => "tunnel",
+            AppError::Query(_) => "query",
+            AppError::Transaction(_) => "transaction",
+            AppError::Io(_) => "io",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::Query(e) => &e.message,
+            AppError::NotFound(message)
+            | AppError::InvalidInput(message)
+            | AppError::Storage(message)
+            | AppError::Encryption(message)
+            | AppError::Locked(message)
+            | AppError::Tls(message)
+            | AppError::Connection(message)
+            | AppError::Authentication(message)
+            | AppError::Tunnel(message)
+            | AppError::Transaction(message)
+            | AppError::Io(message) => message,
+        }
+    }
+
+    /// A failed encrypt or decrypt of a saved secret.
+    pub fn encryption(context: &str, e: String) -> Self {
+        if crate::encryption::is_locked() {
+            AppError::Locked(e)
+        } else {
+            AppError::Encryption(format!("{}: {}", context, e))
+        }
+    }
+
+    /// A failed query or metadata lookup.
+    pub fn query(context: &str, e: tokio_postgres::Error) -> Self {
+        AppError::Query(Box::new(QueryError::new(format!("{}: {}", context, e), &e)))
+    }
+
+    /// A failed connection attempt; SQLSTATE class 28 means the credentials were rejected.
+    pub fn connect(context: &str, e: tokio_postgres::Error) -> Self {
+        let message = format!("{}: {}", context, e);
+        match e.as_db_error() {
+            Some(db) if db.code().code().starts_with("28") => AppError::Authentication(message),
+            _ => AppError::Connection(message),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<QueryError> for AppError {
+    fn from(e: QueryError) -> Self {
+        AppError::Query(Box::new(e))
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut fields = match self {
+            AppError::Query(e) => match serde_json::to_value(e).map_err(S::Error::custom)? {
+                serde_json::Value::Object(fields) => fields,
+                _ => serde_json::Map::new(),
+            },
+            _ => {
+                let mut fields = serde_json::Map::new();
+                fields.insert("message".to_string(), serde_json::Value::String(self.message().to_string()));
+                fields
+            }
+        };
+        fields.insert("kind".to_string(), serde_json::Value::String(self.kind().to_string()));
+
+        let mut map = serializer.serialize_map(Some(fields.len()))?;
+        for (key, value) in &fields {
+            map.serialize_entry(key, value)?;
+        }
+        map.end()
+    }
+}
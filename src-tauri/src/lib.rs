@@ -1,31 +1,55 @@
 use tauri::{Manager, Emitter, menu::*, PhysicalPosition, PhysicalSize};
-use tauri_plugin_store::StoreExt;
 use std::fs::File;
 use std::io::{Write, BufWriter};
 use serde::{Deserialize, Serialize};
-use std::sync::Mutex;
-use std::collections::HashMap;
 use uuid::Uuid;
-use rust_decimal::Decimal;
 
+use error::AppError;
+
+mod bundle;
+mod cancel;
+mod connection;
+mod decode;
 mod encryption;
+mod error;
+mod import;
+mod logging;
+mod notices;
+mod paging;
+mod pool;
+mod query_error;
+mod safety;
+mod script;
+mod session;
+mod tls;
+mod tunnel;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct DatabaseConnection {
     id: String,
     name: String,
+    /// Host name, socket directory, or comma-separated `host[:port]` list tried in order
     host: String,
     port: u16,
     database: String,
     username: String,
     password: Option<String>,
     ssl: Option<bool>,
+    ssl_mode: Option<tls::SslMode>,
+    ssl_root_cert: Option<String>,
+    ssl_client_cert: Option<String>,
+    ssl_client_key: Option<String>,
+    #[serde(flatten)]
+    connect_options: connection::ConnectionOptions,
+    ssh_tunnel: Option<tunnel::SshTunnel>,
     color: Option<String>,
+    #[serde(flatten)]
+    labels: connection::ConnectionLabels,
     created_at: String,
     last_connected: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct CreateConnectionRequest {
     name: String,
     host: String,
@@ -34,7 +58,16 @@ struct CreateConnectionRequest {
     username: String,
     password: String,
     ssl: Option<bool>,
+    ssl_mode: Option<tls::SslMode>,
+    ssl_root_cert: Option<String>,
+    ssl_client_cert: Option<String>,
+    ssl_client_key: Option<String>,
+    #[serde(flatten)]
+    connect_options: connection::ConnectionOptions,
+    ssh_tunnel: Option<tunnel::SshTunnel>,
     color: Option<String>,
+    #[serde(flatten)]
+    labels: connection::ConnectionLabels,
 }
 
 #[derive(Debug, Deserialize)]
@@ -47,7 +80,16 @@ struct UpdateConnectionRequest {
     username: String,
     password: String,
     ssl: Option<bool>,
+    ssl_mode: Option<tls::SslMode>,
+    ssl_root_cert: Option<String>,
+    ssl_client_cert: Option<String>,
+    ssl_client_key: Option<String>,
+    #[serde(flatten)]
+    connect_options: connection::ConnectionOptions,
+    ssh_tunnel: Option<tunnel::SshTunnel>,
     color: Option<String>,
+    #[serde(flatten)]
+    labels: connection::ConnectionLabels,
 }
 
 #[derive(Debug, Deserialize)]
@@ -58,48 +100,91 @@ struct TestConnectionRequest {
     username: String,
     password: String,
     ssl: Option<bool>,
+    ssl_mode: Option<tls::SslMode>,
+    ssl_root_cert: Option<String>,
+    ssl_client_cert: Option<String>,
+    ssl_client_key: Option<String>,
+    #[serde(flatten)]
+    connect_options: connection::ConnectionOptions,
+    ssh_tunnel: Option<tunnel::SshTunnel>,
 }
 
 #[derive(Debug, Serialize)]
 struct TestConnectionResponse {
     success: bool,
-    error: Option<String>,
+    error: Option<AppError>,
 }
 
-// Global state for connections
-static CONNECTIONS: Mutex<Option<HashMap<String, DatabaseConnection>>> = Mutex::new(None);
-static ACTIVE_CONNECTION: Mutex<Option<String>> = Mutex::new(None);
+impl<T> From<Result<T, AppError>> for TestConnectionResponse {
+    fn from(result: Result<T, AppError>) -> Self {
+        match result {
+            Ok(_) => TestConnectionResponse { success: true, error: None },
+            Err(e) => TestConnectionResponse { success: false, error: Some(e) },
+        }
+    }
+}
 
+impl DatabaseConnection {
+    fn tls_settings(&self) -> tls::TlsSettings<'_> {
+        tls::TlsSettings {
+            ssl: self.ssl,
+            ssl_mode: self.ssl_mode,
+            root_cert: self.ssl_root_cert.as_deref(),
+            client_cert: self.ssl_client_cert.as_deref(),
+            client_key: self.ssl_client_key.as_deref(),
+        }
+    }
+}
 
-// Database command functions
-#[tauri::command]
-async fn get_stored_connections(app: tauri::AppHandle) -> Result<Vec<DatabaseConnection>, String> {
-    use tauri_plugin_store::StoreExt;
-    
-    let store = app.store_builder("connections.json").build()
-        .map_err(|e| format!("Failed to build store: {}", e))?;
-    
-    match store.get("connections") {
-        Some(value) => {
-            serde_json::from_value(value.clone())
-                .map_err(|e| format!("Failed to deserialize connections: {}", e))
-        },
-        None => Ok(vec![])
+impl TestConnectionRequest {
+    fn tls_settings(&self) -> tls::TlsSettings<'_> {
+        tls::TlsSettings {
+            ssl: self.ssl,
+            ssl_mode: self.ssl_mode,
+            root_cert: self.ssl_root_cert.as_deref(),
+            client_cert: self.ssl_client_cert.as_deref(),
+            client_key: self.ssl_client_key.as_deref(),
+        }
+    }
+}
+
+/// Use the live session for this connection if there is one, otherwise a pooled client.
+async fn acquire_client(
+    app: &tauri::AppHandle,
+    sessions: &session::SessionManager,
+    pools: &pool::PoolManager,
+    connection_id: &str,
+) -> Result<session::DbClient, AppError> {
+    if let Some(session) = sessions.get(connection_id) {
+        session.end_result_transaction().await;
+        return Ok(session::DbClient::Session(session));
     }
+
+    let connection = connection::resolve_connection(app, connection_id)?;
+    Ok(session::DbClient::Pooled(pools.get(&connection).await?))
 }
 
+// Database command functions
 #[tauri::command]
-async fn save_connection(app: tauri::AppHandle, connection: CreateConnectionRequest) -> Result<DatabaseConnection, String> {
-    use tauri_plugin_store::StoreExt;
-    
-    let store = app.store_builder("connections.json").build()
-        .map_err(|e| format!("Failed to build store: {}", e))?;
-    
-    let encrypted_password = encryption::encrypt_password(&connection.password)
-        .map_err(|e| format!("Failed to encrypt password: {}", e))?;
+async fn get_stored_connections(
+    app: tauri::AppHandle,
+    filter: Option<connection::ConnectionFilter>,
+    sort: Option<connection::ConnectionSort>,
+) -> Result<Vec<DatabaseConnection>, AppError> {
+    let connections = connection::load_connections(&app)?;
+    Ok(connection::filter_and_sort(connections, &filter.unwrap_or_default(), sort))
+}
+
+/// A new stored connection with its secrets encrypted.
+fn new_connection(connection: CreateConnectionRequest) -> Result<DatabaseConnection, AppError> {
+    connection::parse_hosts(&connection.host, connection.port)?;
+    let id = Uuid::new_v4().to_string();
+    let encrypted_password = encryption::encrypt_password(&connection.password, &id, encryption::PASSWORD_FIELD)
+        .map_err(|e| AppError::encryption("Failed to encrypt password", e))?;
+    let ssh_tunnel = connection.ssh_tunnel.map(|ssh| ssh.encrypted(&id)).transpose()?;
     
     let new_connection = DatabaseConnection {
-        id: Uuid::new_v4().to_string(),
+        id,
         name: connection.name,
         host: connection.host,
         port: connection.port,
@@ -107,116 +192,242 @@ async fn save_connection(app: tauri::AppHandle, connection: CreateConnectionRequ
         username: connection.username,
         password: Some(encrypted_password),
         ssl: connection.ssl,
+        ssl_mode: connection.ssl_mode,
+        ssl_root_cert: connection.ssl_root_cert,
+        ssl_client_cert: connection.ssl_client_cert,
+        ssl_client_key: connection.ssl_client_key,
+        connect_options: connection.connect_options,
+        ssh_tunnel,
         color: connection.color,
+        labels: connection.labels.normalized(),
         created_at: chrono::Utc::now().to_rfc3339(),
         last_connected: None,
     };
     
-    let mut connections: Vec<DatabaseConnection> = match store.get("connections") {
-        Some(value) => serde_json::from_value(value.clone()).unwrap_or_default(),
-        None => vec![]
-    };
+    Ok(new_connection)
+}
+
+#[tauri::command]
+async fn save_connection(app: tauri::AppHandle, connection: CreateConnectionRequest) -> Result<DatabaseConnection, AppError> {
+    let _store_lock = connection::lock_store();
+    let new_connection = new_connection(connection)?;
     
+    let mut connections = connection::load_connections(&app)?;
     connections.push(new_connection.clone());
-    
-    let value = serde_json::to_value(&connections)
-        .map_err(|e| format!("Failed to serialize connections: {}", e))?;
-    
-    store.set("connections", value);
-    store.save().map_err(|e| format!("Failed to save store: {}", e))?;
+    connection::save_connections(&app, &connections)?;
     
     Ok(new_connection)
 }
 
 #[tauri::command]
-async fn delete_connection(app: tauri::AppHandle, id: String) -> Result<(), String> {
-    use tauri_plugin_store::StoreExt;
-    
-    let store = app.store_builder("connections.json").build()
-        .map_err(|e| format!("Failed to build store: {}", e))?;
+async fn parse_connection_uri(uri: String) -> Result<CreateConnectionRequest, AppError> {
+    import::parse_uri(&uri)
+}
+
+/// The connections a URI, pgpass or service file would create, to review before importing.
+#[tauri::command]
+async fn preview_connection_import(app: tauri::AppHandle, source: import::ImportSource) -> Result<import::ImportPreview, AppError> {
+    import::preview(&app, &source)
+}
+
+#[tauri::command]
+async fn import_connections(app: tauri::AppHandle, connections: Vec<CreateConnectionRequest>) -> Result<Vec<DatabaseConnection>, AppError> {
+    let _store_lock = connection::lock_store();
+    let imported = connections.into_iter()
+        .map(new_connection)
+        .collect::<Result<Vec<_>, _>>()?;
     
-    let mut connections: Vec<DatabaseConnection> = match store.get("connections") {
-        Some(value) => serde_json::from_value(value.clone()).unwrap_or_default(),
-        None => return Ok(())
-    };
+    let mut stored = connection::load_connections(&app)?;
+    stored.extend(imported.iter().cloned());
+    connection::save_connections(&app, &stored)?;
     
-    connections.retain(|conn| conn.id != id);
+    Ok(imported)
+}
+
+/// Write the selected connections to a file sealed with `passphrase`, to share with teammates.
+#[tauri::command]
+async fn export_connections(
+    app: tauri::AppHandle,
+    path: String,
+    ids: Vec<String>,
+    passphrase: String,
+    include_passwords: bool,
+) -> Result<usize, AppError> {
+    // Key derivation is deliberately slow, keep it off the async runtime
+    tokio::task::spawn_blocking(move || bundle::export(&app, std::path::Path::new(&path), &ids, &passphrase, include_passwords))
+        .await
+        .map_err(|e| AppError::Encryption(format!("Export task failed: {}", e)))?
+}
+
+/// The connections in a bundle and which saved ones they collide with.
+#[tauri::command]
+async fn preview_connection_bundle(app: tauri::AppHandle, path: String, passphrase: String) -> Result<Vec<bundle::BundleEntry>, AppError> {
+    tokio::task::spawn_blocking(move || bundle::preview(&app, std::path::Path::new(&path), &passphrase))
+        .await
+        .map_err(|e| AppError::Encryption(format!("Import task failed: {}", e)))?
+}
+
+#[tauri::command]
+async fn import_connection_bundle(
+    app: tauri::AppHandle,
+    pools: tauri::State<'_, pool::PoolManager>,
+    tunnels: tauri::State<'_, tunnel::TunnelManager>,
+    path: String,
+    passphrase: String,
+    conflict: bundle::ConflictPolicy,
+) -> Result<bundle::BundleImport, AppError> {
+    let result = tokio::task::spawn_blocking(move || bundle::import(&app, std::path::Path::new(&path), &passphrase, conflict))
+        .await
+        .map_err(|e| AppError::Encryption(format!("Import task failed: {}", e)))??;
+    
+    // Pooled clients and tunnels were opened with the replaced settings
+    for id in &result.overwritten {
+        pools.evict(id);
+        tunnels.evict(id);
+    }
     
-    let value = serde_json::to_value(&connections)
-        .map_err(|e| format!("Failed to serialize connections: {}", e))?;
+    Ok(result)
+}
+
+#[tauri::command]
+async fn get_encryption_status() -> Result<encryption::EncryptionStatus, AppError> {
+    Ok(encryption::status())
+}
+
+#[tauri::command]
+async fn unlock_connections(app: tauri::AppHandle, password: String) -> Result<(), AppError> {
+    // Key derivation is deliberately slow, keep it off the async runtime
+    tokio::task::spawn_blocking(move || encryption::unlock(&app, &password))
+        .await
+        .map_err(|e| AppError::Encryption(format!("Unlock task failed: {}", e)))?
+}
+
+#[tauri::command]
+async fn lock_connections() -> Result<(), AppError> {
+    encryption::lock()
+}
+
+#[tauri::command]
+async fn set_master_password(
+    app: tauri::AppHandle,
+    current_password: Option<String>,
+    new_password: String,
+) -> Result<(), AppError> {
+    tokio::task::spawn_blocking(move || {
+        encryption::set_master_password(&app, current_password.as_deref(), &new_password)
+    })
+        .await
+        .map_err(|e| AppError::Encryption(format!("Master password task failed: {}", e)))?
+}
+
+#[tauri::command]
+async fn remove_master_password(app: tauri::AppHandle, current_password: String) -> Result<(), AppError> {
+    tokio::task::spawn_blocking(move || encryption::remove_master_password(&app, &current_password))
+        .await
+        .map_err(|e| AppError::Encryption(format!("Master password task failed: {}", e)))?
+}
+
+#[tauri::command]
+async fn set_auto_lock(app: tauri::AppHandle, minutes: Option<u64>) -> Result<(), AppError> {
+    encryption::set_auto_lock(&app, minutes)
+}
+
+#[tauri::command]
+async fn rotate_master_key(app: tauri::AppHandle) -> Result<(), AppError> {
+    tokio::task::spawn_blocking(move || encryption::rotate_master_key(&app))
+        .await
+        .map_err(|e| AppError::Encryption(format!("Key rotation task failed: {}", e)))?
+}
+
+#[tauri::command]
+async fn delete_connection(
+    app: tauri::AppHandle,
+    sessions: tauri::State<'_, session::SessionManager>,
+    pools: tauri::State<'_, pool::PoolManager>,
+    tunnels: tauri::State<'_, tunnel::TunnelManager>,
+    id: String,
+) -> Result<(), AppError> {
+    {
+        let _store_lock = connection::lock_store();
+        let mut connections = connection::load_connections(&app)?;
+        connections.retain(|conn| conn.id != id);
+        connection::save_connections(&app, &connections)?;
+    }
     
-    store.set("connections", value);
-    store.save().map_err(|e| format!("Failed to save store: {}", e))?;
+    pools.evict(&id);
+    tunnels.evict(&id);
+    if sessions.active_connection_id().as_deref() == Some(id.as_str()) {
+        sessions.close();
+    }
     
     Ok(())
 }
 
 #[tauri::command]
-async fn update_connection(app: tauri::AppHandle, connection: UpdateConnectionRequest) -> Result<DatabaseConnection, String> {
-    use tauri_plugin_store::StoreExt;
-    
-    let store = app.store_builder("connections.json").build()
-        .map_err(|e| format!("Failed to build store: {}", e))?;
-    
-    let mut connections: Vec<DatabaseConnection> = match store.get("connections") {
-        Some(value) => serde_json::from_value(value.clone()).unwrap_or_default(),
-        None => return Err("No connections found".to_string())
+async fn update_connection(
+    app: tauri::AppHandle,
+    pools: tauri::State<'_, pool::PoolManager>,
+    tunnels: tauri::State<'_, tunnel::TunnelManager>,
+    connection: UpdateConnectionRequest,
+) -> Result<DatabaseConnection, AppError> {
+    let updated_connection = {
+        let _store_lock = connection::lock_store();
+        let mut connections = connection::load_connections(&app)?;
+        
+        let conn = connections.iter_mut()
+            .find(|conn| conn.id == connection.id)
+            .ok_or_else(|| AppError::NotFound("Connection not found".to_string()))?;
+        connection::parse_hosts(&connection.host, connection.port)?;
+        
+        let encrypted_password = encryption::encrypt_password(&connection.password, &connection.id, encryption::PASSWORD_FIELD)
+            .map_err(|e| AppError::encryption("Failed to encrypt password", e))?;
+        
+        conn.name = connection.name;
+        conn.host = connection.host;
+        conn.port = connection.port;
+        conn.database = connection.database;
+        conn.username = connection.username;
+        conn.password = Some(encrypted_password);
+        conn.ssl = connection.ssl;
+        conn.ssl_mode = connection.ssl_mode;
+        conn.ssl_root_cert = connection.ssl_root_cert;
+        conn.ssl_client_cert = connection.ssl_client_cert;
+        conn.ssl_client_key = connection.ssl_client_key;
+        conn.connect_options = connection.connect_options;
+        conn.ssh_tunnel = connection.ssh_tunnel.map(|ssh| ssh.encrypted(&connection.id)).transpose()?;
+        conn.color = connection.color;
+        conn.labels = connection.labels.normalized();
+        let updated_connection = conn.clone();
+        
+        connection::save_connections(&app, &connections)?;
+        updated_connection
     };
     
-    let mut updated_connection = None;
-    for conn in &mut connections {
-        if conn.id == connection.id {
-            let encrypted_password = encryption::encrypt_password(&connection.password)
-                .map_err(|e| format!("Failed to encrypt password: {}", e))?;
-            
-            conn.name = connection.name;
-            conn.host = connection.host;
-            conn.port = connection.port;
-            conn.database = connection.database;
-            conn.username = connection.username;
-            conn.password = Some(encrypted_password);
-            conn.ssl = connection.ssl;
-            conn.color = connection.color;
-            updated_connection = Some(conn.clone());
-            break;
-        }
-    }
+    // Pooled clients and the tunnel were opened with the old settings
+    pools.evict(&updated_connection.id);
+    tunnels.evict(&updated_connection.id);
     
-    match updated_connection {
-        Some(conn) => {
-            let value = serde_json::to_value(&connections)
-                .map_err(|e| format!("Failed to serialize connections: {}", e))?;
-            
-            store.set("connections", value);
-            store.save().map_err(|e| format!("Failed to save store: {}", e))?;
-            
-            Ok(conn)
-        },
-        None => Err("Connection not found".to_string())
-    }
+    Ok(updated_connection)
 }
 
 #[tauri::command]
-async fn test_database_connection(connection: TestConnectionRequest) -> Result<TestConnectionResponse, String> {
-    let ssl_mode = if connection.ssl.unwrap_or(false) { "require" } else { "disable" };
-    
-    let config = format!(
-        "host={} port={} dbname={} user={} password={} sslmode={}",
-        connection.host,
-        connection.port,
-        connection.database,
-        connection.username,
-        connection.password,
-        ssl_mode
-    );
-    
-    match tokio_postgres::connect(&config, tokio_postgres::NoTls).await {
-        Ok(_) => Ok(TestConnectionResponse { success: true, error: None }),
-        Err(e) => Ok(TestConnectionResponse { 
-            success: false, 
-            error: Some(e.to_string()) 
-        })
-    }
+async fn test_database_connection(connection: TestConnectionRequest) -> Result<TestConnectionResponse, AppError> {
+    let target = connection::ConnectTarget {
+        host: &connection.host,
+        port: connection.port,
+        database: &connection.database,
+        username: &connection.username,
+        password: connection.password.clone(),
+        tls: connection.tls_settings(),
+        options: &connection.connect_options,
+        tunnel: None,
+    };
+    
+    let result = match target.with_tunnel(connection.ssh_tunnel.as_ref()).await {
+        Ok(target) => connection::open(&target, None).await,
+        Err(e) => Err(e),
+    };
+    Ok(TestConnectionResponse::from(result))
 }
 
 #[derive(Debug, Deserialize)]
@@ -227,6 +438,13 @@ struct CreateDatabaseRequest {
     username: String,
     password: String,
     ssl: Option<bool>,
+    ssl_mode: Option<tls::SslMode>,
+    ssl_root_cert: Option<String>,
+    ssl_client_cert: Option<String>,
+    ssl_client_key: Option<String>,
+    #[serde(flatten)]
+    connect_options: connection::ConnectionOptions,
+    ssh_tunnel: Option<tunnel::SshTunnel>,
     // New database details
     new_database_name: String,
     encoding: Option<String>,
@@ -235,34 +453,33 @@ struct CreateDatabaseRequest {
 }
 
 #[tauri::command]
-async fn create_database(request: CreateDatabaseRequest) -> Result<String, String> {
-    println!("Creating database: {}", request.new_database_name);
+async fn create_database(request: CreateDatabaseRequest) -> Result<String, AppError> {
+    log::info!("Creating database: {}", request.new_database_name);
 
     // Validate database name - basic SQL injection prevention
     if !request.new_database_name.chars().all(|c| c.is_alphanumeric() || c == '_') {
-        return Err("Database name can only contain alphanumeric characters and underscores".to_string());
+        return Err(AppError::InvalidInput("Database name can only contain alphanumeric characters and underscores".to_string()));
     }
 
-    let ssl_mode = if request.ssl.unwrap_or(false) { "require" } else { "disable" };
-
     // Connect to postgres database to create the new database
-    let config = format!(
-        "host={} port={} dbname=postgres user={} password={} sslmode={}",
-        request.host,
-        request.port,
-        request.username,
-        request.password,
-        ssl_mode
-    );
-
-    let (client, conn) = tokio_postgres::connect(&config, tokio_postgres::NoTls).await
-        .map_err(|e| format!("Failed to connect to PostgreSQL server: {}", e))?;
-
-    tokio::spawn(async move {
-        if let Err(e) = conn.await {
-            eprintln!("Connection error: {}", e);
-        }
-    });
+    let target = connection::ConnectTarget {
+        host: &request.host,
+        port: request.port,
+        database: "postgres",
+        username: &request.username,
+        password: request.password.clone(),
+        tls: tls::TlsSettings {
+            ssl: request.ssl,
+            ssl_mode: request.ssl_mode,
+            root_cert: request.ssl_root_cert.as_deref(),
+            client_cert: request.ssl_client_cert.as_deref(),
+            client_key: request.ssl_client_key.as_deref(),
+        },
+        options: &request.connect_options,
+        tunnel: None,
+    };
+    let target = target.with_tunnel(request.ssh_tunnel.as_ref()).await?;
+    let (client, _driver) = connection::open(&target, None).await?;
 
     // Build CREATE DATABASE command with options
     let mut create_db_sql = format!("CREATE DATABASE \"{}\"", request.new_database_name);
@@ -289,41 +506,53 @@ async fn create_database(request: CreateDatabaseRequest) -> Result<String, Strin
         .map_err(|e| {
             // Provide user-friendly error messages
             let error_msg = e.to_string();
-            if error_msg.contains("already exists") {
+            let context = if error_msg.contains("already exists") {
                 format!("Database '{}' already exists", request.new_database_name)
             } else if error_msg.contains("permission denied") {
                 format!("Permission denied: User '{}' does not have permission to create databases", request.username)
             } else {
                 format!("Failed to create database: {}", e)
-            }
+            };
+            AppError::from(query_error::QueryError::new(context, &e))
         })?;
 
     Ok(format!("Database '{}' created successfully", request.new_database_name))
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn list_databases(
     host: String,
     port: u16,
     username: String,
     password: String,
-    ssl: Option<bool>
-) -> Result<Vec<String>, String> {
-    let ssl_mode = if ssl.unwrap_or(false) { "require" } else { "disable" };
-
-    let config = format!(
-        "host={} port={} dbname=postgres user={} password={} sslmode={}",
-        host, port, username, password, ssl_mode
-    );
-
-    let (client, conn) = tokio_postgres::connect(&config, tokio_postgres::NoTls).await
-        .map_err(|e| format!("Failed to connect: {}", e))?;
-
-    tokio::spawn(async move {
-        if let Err(e) = conn.await {
-            eprintln!("Connection error: {}", e);
-        }
-    });
+    ssl: Option<bool>,
+    ssl_mode: Option<tls::SslMode>,
+    ssl_root_cert: Option<String>,
+    ssl_client_cert: Option<String>,
+    ssl_client_key: Option<String>,
+    connect_options: Option<connection::ConnectionOptions>,
+    ssh_tunnel: Option<tunnel::SshTunnel>,
+) -> Result<Vec<String>, AppError> {
+    let connect_options = connect_options.unwrap_or_default();
+    let target = connection::ConnectTarget {
+        host: &host,
+        port,
+        database: "postgres",
+        username: &username,
+        password,
+        tls: tls::TlsSettings {
+            ssl,
+            ssl_mode,
+            root_cert: ssl_root_cert.as_deref(),
+            client_cert: ssl_client_cert.as_deref(),
+            client_key: ssl_client_key.as_deref(),
+        },
+        options: &connect_options,
+        tunnel: None,
+    };
+    let target = target.with_tunnel(ssh_tunnel.as_ref()).await?;
+    let (client, _driver) = connection::open(&target, None).await?;
 
     // Query for all databases the user can connect to
     let query = "SELECT datname FROM pg_database
@@ -332,7 +561,7 @@ async fn list_databases(
                  ORDER BY datname";
 
     let rows = client.query(query, &[]).await
-        .map_err(|e| format!("Failed to list databases: {}", e))?;
+        .map_err(|e| AppError::query("Failed to list databases", e))?;
 
     let databases: Vec<String> = rows.iter()
         .map(|row| row.get::<_, String>(0))
@@ -342,300 +571,470 @@ async fn list_databases(
 }
 
 #[tauri::command]
-async fn test_stored_connection(connection: DatabaseConnection) -> Result<TestConnectionResponse, String> {
-    println!("Testing stored connection: {}", connection.name);
-    
-    // Decrypt password if it's encrypted
-    let password = match &connection.password {
-        Some(pwd) => {
-            println!("Password present, checking if encrypted...");
-            if encryption::is_encrypted(pwd) {
-                println!("Password is encrypted, decrypting...");
-                match encryption::decrypt_password(pwd) {
-                    Ok(decrypted) => {
-                        println!("Password decrypted successfully");
-                        decrypted
-                    },
-                    Err(e) => {
-                        println!("Failed to decrypt password: {}", e);
-                        return Err(format!("Failed to decrypt password: {}", e));
-                    }
-                }
-            } else {
-                println!("Password is not encrypted, using as-is");
-                pwd.clone()
-            }
-        },
-        None => String::new(),
+async fn test_stored_connection(connection: DatabaseConnection) -> Result<TestConnectionResponse, AppError> {
+    log::info!("Testing stored connection: {}", connection.name);
+    
+    let target = connection::ConnectTarget::from_stored(&connection)?;
+    let ssh_tunnel = connection.ssh_tunnel.as_ref()
+        .map(|ssh| ssh.decrypted(&connection.id))
+        .transpose()?;
+    let result = match target.with_tunnel(ssh_tunnel.as_ref()).await {
+        Ok(target) => connection::open(&target, None).await,
+        Err(e) => Err(e),
     };
-    
-    let ssl_mode = if connection.ssl.unwrap_or(false) { "require" } else { "disable" };
-    
-    let config = format!(
-        "host={} port={} dbname={} user={} password={} sslmode={}",
-        connection.host,
-        connection.port,
-        connection.database,
-        connection.username,
-        password,
-        ssl_mode
-    );
-    
-    match tokio_postgres::connect(&config, tokio_postgres::NoTls).await {
-        Ok(_) => Ok(TestConnectionResponse { success: true, error: None }),
-        Err(e) => Ok(TestConnectionResponse { 
-            success: false, 
-            error: Some(e.to_string()) 
-        })
+    Ok(TestConnectionResponse::from(result))
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct QueryStartedPayload {
+    query_id: String,
+    connection_id: String,
+}
+
+/// Register an in-flight query so `cancel_query` can stop it and server notices
+/// are attributed to it, and tell the frontend which id to cancel it by. Both
+/// last until the returned guards are dropped.
+fn start_query<'a>(
+    app: &tauri::AppHandle,
+    queries: &'a cancel::RunningQueries,
+    query_id: Option<String>,
+    connection_id: &str,
+    client: &session::DbClient,
+) -> (cancel::QueryGuard<'a>, notices::NoticeScope) {
+    let query_id = query_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+    let guard = queries.register(&query_id, connection_id, client.cancel_token());
+    let notices = client.notices().start(&query_id);
+
+    let payload = QueryStartedPayload {
+        query_id,
+        connection_id: connection_id.to_string(),
+    };
+    if let Err(e) = app.emit("query-started", payload) {
+        log::error!("Failed to emit query-started: {}", e);
     }
+
+    (guard, notices)
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct QueryRowsPayload<'a> {
+    query_id: Option<&'a str>,
+    result_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    columns: Option<&'a [decode::ColumnInfo]>,
+    rows: Vec<serde_json::Value>,
+}
+
+/// Read the next page of an open result as positional row arrays. With
+/// `stream_rows` the rows are only emitted as `query-rows` events in batches
+/// (the first batch carrying `columns`, if given); otherwise they are returned.
+async fn read_result_page(
+    app: &tauri::AppHandle,
+    open_result: &mut paging::OpenResult,
+    query_id: Option<&str>,
+    result_id: &str,
+    columns: Option<&[decode::ColumnInfo]>,
+    page_size: usize,
+    stream_rows: bool,
+) -> Result<(Vec<serde_json::Value>, paging::Page), tokio_postgres::Error> {
+    let mut collected = Vec::new();
+    let mut columns = columns;
+
+    let page = open_result.read_page(page_size, |batch| {
+        let rows: Vec<serde_json::Value> = batch.iter().map(decode::row_to_array).collect();
+        if stream_rows {
+            let payload = QueryRowsPayload { query_id, result_id, columns: columns.take(), rows };
+            if let Err(e) = app.emit("query-rows", payload) {
+                log::error!("Failed to emit query-rows: {}", e);
+            }
+        } else {
+            collected.extend(rows);
+        }
+    }).await?;
+
+    Ok((collected, page))
+}
+
+fn insert_page_metadata(
+    metadata: &mut serde_json::Map<String, serde_json::Value>,
+    page: &paging::Page,
+    page_size: u32,
+    result_id: Option<String>,
+    command_tag: Option<String>,
+) {
+    metadata.insert("total_rows".to_string(), serde_json::Value::Number(page.row_count.into()));
+    metadata.insert("returned_rows".to_string(), serde_json::Value::Number(page.row_count.into()));
+    metadata.insert("limit_applied".to_string(), serde_json::Value::Bool(page.has_more));
+    metadata.insert("result_limit".to_string(), serde_json::Value::Number(page_size.into()));
+    metadata.insert("has_more".to_string(), serde_json::Value::Bool(page.has_more));
+    metadata.insert("result_id".to_string(), result_id.map(serde_json::Value::String).unwrap_or(serde_json::Value::Null));
+    // Only known once the last page has been read
+    metadata.insert("command_tag".to_string(), command_tag.map(serde_json::Value::String).unwrap_or(serde_json::Value::Null));
 }
 
 #[tauri::command]
-async fn execute_query(app: tauri::AppHandle, connection_id: String, sql: String, limit: Option<u32>) -> Result<serde_json::Value, String> {
-    println!("Executing query for connection: {}", connection_id);
-    println!("SQL: {}", sql);
-    
-    // Load connections from store to get the connection details
-    let store = app.store_builder("connections.json").build()
-        .map_err(|e| format!("Failed to build store: {}", e))?;
-    
-    let connections: Vec<DatabaseConnection> = store.get("connections")
-        .and_then(|value| serde_json::from_value(value).ok())
-        .unwrap_or_default();
-    
-    let connection = connections.iter()
-        .find(|c| c.id == connection_id)
-        .ok_or("Connection not found")?;
-    
-    // Decrypt password if it's encrypted
-    let password = match &connection.password {
-        Some(encrypted) if encryption::is_encrypted(encrypted) => {
-            encryption::decrypt_password(encrypted)?
+async fn fetch_next_page(
+    app: tauri::AppHandle,
+    cursors: tauri::State<'_, paging::ResultCursors>,
+    result_id: String,
+    page_size: Option<u32>,
+    stream: Option<bool>,
+) -> Result<serde_json::Value, AppError> {
+    let mut open_result = cursors.take(&result_id)
+        .ok_or_else(|| AppError::NotFound("Result is no longer open".to_string()))?;
+
+    // A result read inside a transaction ends with it
+    let is_open = open_result.is_open().await
+        .map_err(|e| AppError::query("Failed to fetch rows", e))?;
+    if !is_open {
+        return Err(AppError::NotFound("Result was closed when its transaction ended".to_string()));
+    }
+
+    let page_size = page_size.unwrap_or(1000);
+    let read = read_result_page(&app, &mut open_result, None, &result_id, None, page_size as usize, stream.unwrap_or(false)).await;
+    let (rows, page) = match read {
+        Ok(read) => read,
+        Err(e) => {
+            open_result.client().track_statement("FETCH", false);
+            open_result.close().await;
+            return Err(AppError::query("Failed to fetch rows", e));
         },
-        Some(plain) => plain.clone(),
-        None => String::new(),
     };
-    
-    let ssl_mode = if connection.ssl.unwrap_or(false) { "require" } else { "disable" };
-    
-    let config = format!(
-        "host={} port={} dbname={} user={} password={} sslmode={}",
-        connection.host,
-        connection.port,
-        connection.database,
-        connection.username,
-        password,
-        ssl_mode
-    );
-    
-    // Connect and execute query
-    let (client, conn) = tokio_postgres::connect(&config, tokio_postgres::NoTls).await
-        .map_err(|e| format!("Connection failed: {}", e))?;
-    
-    // Spawn connection handler
-    tokio::spawn(async move {
-        if let Err(e) = conn.await {
-            eprintln!("Connection error: {}", e);
-        }
-    });
-    
-    // Detect if this is a SELECT query or a DDL/DML statement
-    // Remove comments and extra whitespace first
-    let sql_cleaned = sql.lines()
-        .map(|line| {
-            // Remove single-line comments (-- comment)
-            if let Some(pos) = line.find("--") {
-                &line[..pos]
-            } else {
-                line
-            }
-        })
-        .collect::<Vec<_>>()
-        .join("\n")
-        .trim()
-        .to_uppercase();
 
-    let is_select = sql_cleaned.starts_with("SELECT") ||
-                   sql_cleaned.starts_with("WITH") ||
-                   sql_cleaned.starts_with("SHOW") ||
-                   sql_cleaned.starts_with("EXPLAIN");
-    
-    let result_limit = limit.unwrap_or(1000); // Default limit of 1000 rows
-    let mut results = Vec::new();
+    let command_tag = open_result.command_tag();
+    if page.has_more {
+        cursors.insert(&result_id, open_result);
+    } else {
+        open_result.close().await;
+    }
+
+    let mut metadata = serde_json::Map::new();
+    insert_page_metadata(&mut metadata, &page, page_size, page.has_more.then_some(result_id), command_tag);
+
+    let mut response = serde_json::Map::new();
+    response.insert("rows".to_string(), serde_json::Value::Array(rows));
+    response.insert("metadata".to_string(), serde_json::Value::Object(metadata));
+
+    Ok(serde_json::Value::Object(response))
+}
+
+#[tauri::command]
+async fn close_result(
+    cursors: tauri::State<'_, paging::ResultCursors>,
+    result_id: String,
+) -> Result<(), AppError> {
+    if let Some(open_result) = cursors.take(&result_id) {
+        open_result.close().await;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn cancel_query(
+    app: tauri::AppHandle,
+    queries: tauri::State<'_, cancel::RunningQueries>,
+    query_id: String,
+) -> Result<(), AppError> {
+    let (connection_id, token) = queries.begin_cancel(&query_id)
+        .ok_or_else(|| AppError::NotFound("Query is not running".to_string()))?;
+
+    let connection = connection::resolve_connection(&app, &connection_id)?;
+    let tls = connection::tls_connector(&connection)?;
+
+    token.cancel_query(tls).await
+        .map_err(|e| AppError::Connection(format!("Failed to cancel query: {}", e)))
+}
+
+/// What a statement leaves behind: the client, or a partly-read result still holding it.
+enum StatementClient {
+    Ready(session::DbClient),
+    Open(String, Box<paging::OpenResult>),
+}
+
+/// Run a single statement. Statements the server describes with columns
+/// return `columns` and the first page of positional `rows`; other statements
+/// a status with their command tag in `results`.
+async fn run_statement(
+    app: &tauri::AppHandle,
+    query: &cancel::QueryGuard<'_>,
+    client: session::DbClient,
+    connection_id: &str,
+    sql: &str,
+    result_limit: u32,
+    stream_rows: bool,
+) -> (StatementClient, Result<serde_json::Map<String, serde_json::Value>, query_error::QueryError>) {
+    let command = script::command_name(sql);
+    let mut response = serde_json::Map::new();
     let mut metadata = serde_json::Map::new();
     
-    if is_select {
-        // Use query() for SELECT statements that return rows
-        let rows = client.query(&sql, &[]).await
-            .map_err(|e| format!("Query execution failed: {}", e))?;
+    // The server's row description says whether rows come back, e.g. for
+    // VALUES, TABLE, INSERT ... RETURNING or CALL with output parameters
+    let statement = match client.prepare(sql).await {
+        Ok(statement) => statement,
+        Err(e) => {
+            client.track_statement(sql, false);
+            return (StatementClient::Ready(client), Err(query.query_error("Query execution failed", e)));
+        }
+    };
+    
+    let client = if !statement.columns().is_empty() {
+        let columns = decode::ColumnInfo::from_columns(statement.columns());
         
-        let total_rows = rows.len();
-        let limited_rows = if total_rows > result_limit as usize {
-            &rows[0..result_limit as usize]
+        // Plain queries are read through a cursor, so later pages wait on the server
+        // without holding up the client. Anything else, like INSERT ... RETURNING, is
+        // run to the end now rather than leave its changes half done, keeping only
+        // the first page of rows.
+        let plain = script::is_plain_query(sql);
+        let mut open_result = if plain {
+            let cursor = paging::cursor_name();
+            let in_transaction = client.in_transaction();
+            let open = paging::open_cursor(&cursor, sql, in_transaction);
+            let opened = client.batch_execute(&open).await;
+            client.track_statement(sql, opened.is_ok());
+            if let Err(e) = opened {
+                if !in_transaction {
+                    if let Err(e) = client.batch_execute("ROLLBACK").await {
+                        log::warn!("Failed to roll back cursor transaction: {}", e);
+                    }
+                }
+                let prefix = (open.len() - sql.len()) as u32;
+                return (StatementClient::Ready(client), Err(query.query_error("Query execution failed", e).strip_prefix(prefix)));
+            }
+            paging::OpenResult::from_cursor(connection_id, client, cursor, !in_transaction)
         } else {
-            &rows[..]
+            let stream = client.query_raw(&statement, std::iter::empty::<&str>()).await;
+            client.track_statement(sql, stream.is_ok());
+            match stream {
+                Ok(stream) => paging::OpenResult::new(connection_id, command, client, stream),
+                Err(e) => return (StatementClient::Ready(client), Err(query.query_error("Query execution failed", e))),
+            }
         };
         
-        // Add metadata about the results
-        metadata.insert("total_rows".to_string(), serde_json::Value::Number(total_rows.into()));
-        metadata.insert("returned_rows".to_string(), serde_json::Value::Number(limited_rows.len().into()));
-        metadata.insert("limit_applied".to_string(), serde_json::Value::Bool(total_rows > result_limit as usize));
-        metadata.insert("result_limit".to_string(), serde_json::Value::Number(result_limit.into()));
-        
-        // Convert rows to JSON - simplified approach
-        for row in limited_rows {
-            let mut row_map = serde_json::Map::new();
-
-            for (i, column) in row.columns().iter().enumerate() {
-                let column_name = column.name();
-
-                // Try to get the value as different types, falling back to string
-                let value = if let Ok(v) = row.try_get::<_, Option<bool>>(i) {
-                    v.map(serde_json::Value::Bool).unwrap_or(serde_json::Value::Null)
-                } else if let Ok(v) = row.try_get::<_, Option<i16>>(i) {
-                    v.map(|n| serde_json::Value::Number(n.into())).unwrap_or(serde_json::Value::Null)
-                } else if let Ok(v) = row.try_get::<_, Option<i32>>(i) {
-                    v.map(|n| serde_json::Value::Number(n.into())).unwrap_or(serde_json::Value::Null)
-                } else if let Ok(v) = row.try_get::<_, Option<i64>>(i) {
-                    v.map(|n| serde_json::Value::Number(n.into())).unwrap_or(serde_json::Value::Null)
-                } else if let Ok(v) = row.try_get::<_, Option<Decimal>>(i) {
-                    // Handle PostgreSQL NUMERIC/DECIMAL types
-                    match v {
-                        Some(d) => {
-                            // Convert Decimal to string and then parse as serde_json::Number
-                            let d_str = d.to_string();
-                            if let Ok(num) = d_str.parse::<serde_json::Number>() {
-                                serde_json::Value::Number(num)
-                            } else {
-                                // If parsing fails, return as string
-                                serde_json::Value::String(d_str)
-                            }
-                        },
-                        None => serde_json::Value::Null
-                    }
-                } else if let Ok(v) = row.try_get::<_, Option<f32>>(i) {
-                    v.and_then(|n| serde_json::Number::from_f64(n as f64))
-                     .map(serde_json::Value::Number)
-                     .unwrap_or(serde_json::Value::Null)
-                } else if let Ok(v) = row.try_get::<_, Option<f64>>(i) {
-                    v.and_then(|n| serde_json::Number::from_f64(n))
-                     .map(serde_json::Value::Number)
-                     .unwrap_or(serde_json::Value::Null)
-                } else if let Ok(v) = row.try_get::<_, Option<String>>(i) {
-                    v.map(serde_json::Value::String).unwrap_or(serde_json::Value::Null)
-                } else {
-                    // For any other type, fallback to null
-                    serde_json::Value::Null
-                };
-
-                row_map.insert(column_name.to_string(), value);
+        let result_id = paging::ResultCursors::new_result_id();
+        let read = read_result_page(app, &mut open_result, Some(query.query_id()), &result_id, Some(&columns), result_limit as usize, stream_rows).await;
+        let truncated = !plain && matches!(read, Ok((_, ref page)) if page.has_more);
+        let read = match read {
+            Ok(read) if truncated => open_result.discard().await.map(|()| read),
+            read => read,
+        };
+        let (rows, page) = match read {
+            Ok(read) => read,
+            Err(e) => {
+                // Errors can also surface while rows are read, e.g. a division by zero on row 500
+                open_result.client().track_statement(sql, false);
+                return (StatementClient::Ready(open_result.close().await), Err(query.query_error("Query execution failed", e)));
             }
-
-            results.push(serde_json::Value::Object(row_map));
+        };
+        
+        let result_id = (page.has_more && !truncated).then_some(result_id);
+        insert_page_metadata(&mut metadata, &page, result_limit, result_id.clone(), open_result.command_tag());
+        if truncated {
+            // The rest was counted but not kept, and can't be loaded later
+            let total_rows = open_result.total_rows().unwrap_or(page.row_count as u64);
+            metadata.insert("total_rows".to_string(), serde_json::Value::Number(total_rows.into()));
+            metadata.insert("has_more".to_string(), serde_json::Value::Bool(false));
+            metadata.insert("truncated".to_string(), serde_json::Value::Bool(true));
+        }
+        response.insert("columns".to_string(), serde_json::json!(columns));
+        response.insert("rows".to_string(), serde_json::Value::Array(rows));
+        
+        if let Some(result_id) = result_id {
+            StatementClient::Open(result_id, Box::new(open_result))
+        } else {
+            StatementClient::Ready(open_result.close().await)
         }
     } else {
-        // Use execute() for DDL/DML statements that don't return rows
-        let affected_rows = client.execute(&sql, &[]).await
-            .map_err(|e| format!("Query execution failed: {}", e))?;
+        let affected_rows = client.execute(&statement, &[]).await;
+        client.track_statement(sql, affected_rows.is_ok());
+        let affected_rows = match affected_rows {
+            Ok(affected_rows) => affected_rows,
+            Err(e) => return (StatementClient::Ready(client), Err(query.query_error("Query execution failed", e))),
+        };
         
         // Return a success message with affected row count
         let mut success_map = serde_json::Map::new();
         success_map.insert("status".to_string(), serde_json::Value::String("success".to_string()));
         success_map.insert("message".to_string(), serde_json::Value::String("Query executed successfully".to_string()));
         success_map.insert("affected_rows".to_string(), serde_json::Value::Number(affected_rows.into()));
+        success_map.insert("command_tag".to_string(), serde_json::Value::String(script::command_tag(&command, affected_rows)));
         
-        // Determine query type for better messaging
-        let query_type = if sql_cleaned.starts_with("CREATE") {
-            "CREATE"
-        } else if sql_cleaned.starts_with("DROP") {
-            "DROP"
-        } else if sql_cleaned.starts_with("ALTER") {
-            "ALTER"
-        } else if sql_cleaned.starts_with("INSERT") {
-            "INSERT"
-        } else if sql_cleaned.starts_with("UPDATE") {
-            "UPDATE"
-        } else if sql_cleaned.starts_with("DELETE") {
-            "DELETE"
-        } else if sql_cleaned.starts_with("TRUNCATE") {
-            "TRUNCATE"
-        } else {
-            "DDL/DML"
+        // The first keyword, e.g. CREATE for CREATE TABLE ... AS, or the main command after WITH
+        let query_type = match script::leading_keywords(sql, 1).pop() {
+            Some(keyword) if keyword != "WITH" => keyword,
+            _ => command.split(' ').next().unwrap_or_default().to_string(),
+        };
+        success_map.insert("query_type".to_string(), serde_json::Value::String(query_type));
+        response.insert("results".to_string(), serde_json::Value::Array(vec![serde_json::Value::Object(success_map)]));
+        StatementClient::Ready(client)
+    };
+    
+    response.insert("metadata".to_string(), serde_json::Value::Object(metadata));
+    (client, Ok(response))
+}
+
+/// Characters from the start of `sql` to where `statement`, a slice of it, begins.
+fn char_offset(sql: &str, statement: &str) -> u32 {
+    let start = (statement.as_ptr() as usize).saturating_sub(sql.as_ptr() as usize).min(sql.len());
+    sql[..start].chars().count() as u32
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn execute_query(
+    app: tauri::AppHandle,
+    sessions: tauri::State<'_, session::SessionManager>,
+    pools: tauri::State<'_, pool::PoolManager>,
+    queries: tauri::State<'_, cancel::RunningQueries>,
+    cursors: tauri::State<'_, paging::ResultCursors>,
+    confirmations: tauri::State<'_, safety::PendingConfirmations>,
+    connection_id: String,
+    sql: String,
+    limit: Option<u32>,
+    query_id: Option<String>,
+    stream: Option<bool>,
+    stop_on_error: Option<bool>,
+    confirmation_token: Option<String>,
+) -> Result<serde_json::Value, AppError> {
+    log::info!("Executing query for connection: {}", connection_id);
+    log::info!("SQL: {}", logging::sql(&sql));
+    
+    let statements = script::split_statements(&sql);
+    
+    // Destructive statements only run when sent again with the token from this response
+    let connection = connection::resolve_connection(&app, &connection_id)?;
+    if safety::SafetyPolicy::of(&connection) == safety::SafetyPolicy::ConfirmDestructive {
+        let destructive = safety::destructive_statements(&statements);
+        let confirmed = confirmation_token.is_some_and(|token| confirmations.redeem(&token, &connection_id, &sql));
+        if !destructive.is_empty() && !confirmed {
+            log::warn!("Holding {} destructive statement(s) for confirmation", destructive.len());
+            return Ok(serde_json::json!({
+                "needs_confirmation": true,
+                "confirmation_token": confirmations.issue(&connection_id, &sql),
+                "destructive_statements": destructive,
+            }));
+        }
+    }
+    
+    let client = acquire_client(&app, &sessions, &pools, &connection_id).await?;
+    let (query, notices) = start_query(&app, &queries, query_id, &connection_id, &client);
+    
+    let result_limit = limit.unwrap_or(1000); // Default limit of 1000 rows
+    let stream_rows = stream.unwrap_or(false);
+    let started = std::time::Instant::now();
+    
+    if statements.len() <= 1 {
+        let statement = statements.first().copied().unwrap_or(&sql);
+        let (client, response) = run_statement(&app, &query, client, &connection_id, statement, result_limit, stream_rows).await;
+        let mut response = response.map_err(|e| e.offset_position(char_offset(&sql, statement)))?;
+        response.insert("notices".to_string(), serde_json::json!(notices.take()));
+        
+        // Keep the rest of the result around for fetch_next_page
+        if let StatementClient::Open(result_id, open_result) = client {
+            cursors.insert(&result_id, *open_result);
+        }
+        
+        if let Some(serde_json::Value::Object(metadata)) = response.get_mut("metadata") {
+            metadata.insert("query_id".to_string(), serde_json::Value::String(query.query_id().to_string()));
+            metadata.insert("execution_time_ms".to_string(), serde_json::Value::Number((started.elapsed().as_millis() as u64).into()));
+        }
+        return Ok(serde_json::Value::Object(response));
+    }
+    
+    // A script: run each statement in order on the same client, each with its own result
+    let stop_on_error = stop_on_error.unwrap_or(true);
+    let mut client = Some(client);
+    let mut statement_results = Vec::new();
+    let mut failed_count = 0;
+    
+    for (index, statement) in statements.iter().enumerate() {
+        let Some(current) = client.take() else { break };
+        let statement_started = std::time::Instant::now();
+        let (remaining, outcome) = run_statement(&app, &query, current, &connection_id, statement, result_limit, stream_rows).await;
+        
+        let mut failed = outcome.is_err();
+        let mut entry = outcome.unwrap_or_else(|e| {
+            failed_count += 1;
+            let mut entry = serde_json::Map::new();
+            entry.insert("error".to_string(), serde_json::json!(e.offset_position(char_offset(&sql, statement))));
+            entry
+        });
+        
+        entry.insert("notices".to_string(), serde_json::json!(notices.take()));
+        
+        // Only the last statement's result stays open for paging. Earlier ones run
+        // to the end with their remaining rows dropped; canceling them would abort
+        // the transaction the rest of the script runs in.
+        client = match remaining {
+            StatementClient::Ready(current) => Some(current),
+            StatementClient::Open(result_id, open_result) if index + 1 == statements.len() => {
+                cursors.insert(&result_id, *open_result);
+                None
+            },
+            StatementClient::Open(_, mut open_result) => {
+                if let Err(e) = open_result.discard().await {
+                    open_result.client().track_statement(statement, false);
+                    failed = true;
+                    failed_count += 1;
+                    entry.insert("error".to_string(), serde_json::json!(query.query_error("Query execution failed", e).offset_position(char_offset(&sql, statement))));
+                }
+                if let Some(serde_json::Value::Object(metadata)) = entry.get_mut("metadata") {
+                    metadata.insert("result_id".to_string(), serde_json::Value::Null);
+                    metadata.insert("command_tag".to_string(), serde_json::json!(open_result.command_tag()));
+                }
+                Some(open_result.close().await)
+            },
         };
         
-        success_map.insert("query_type".to_string(), serde_json::Value::String(query_type.to_string()));
-        results.push(serde_json::Value::Object(success_map));
+        entry.insert("statement".to_string(), serde_json::Value::String(statement.to_string()));
+        entry.insert("execution_time_ms".to_string(), serde_json::Value::Number((statement_started.elapsed().as_millis() as u64).into()));
+        statement_results.push(serde_json::Value::Object(entry));
+        
+        if failed && (stop_on_error || query.is_canceled()) {
+            break;
+        }
     }
     
-    // Return results with metadata
+    let mut metadata = serde_json::Map::new();
+    metadata.insert("query_id".to_string(), serde_json::Value::String(query.query_id().to_string()));
+    metadata.insert("statement_count".to_string(), serde_json::Value::Number(statements.len().into()));
+    metadata.insert("executed_count".to_string(), serde_json::Value::Number(statement_results.len().into()));
+    metadata.insert("failed_count".to_string(), serde_json::Value::Number(failed_count.into()));
+    metadata.insert("execution_time_ms".to_string(), serde_json::Value::Number((started.elapsed().as_millis() as u64).into()));
+    
     let mut response = serde_json::Map::new();
-    response.insert("results".to_string(), serde_json::Value::Array(results));
+    response.insert("statements".to_string(), serde_json::Value::Array(statement_results));
     response.insert("metadata".to_string(), serde_json::Value::Object(metadata));
     
     Ok(serde_json::Value::Object(response))
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn export_query_stream(
     app: tauri::AppHandle,
+    pools: tauri::State<'_, pool::PoolManager>,
+    queries: tauri::State<'_, cancel::RunningQueries>,
     connection_id: String,
     sql: String,
     output_path: String,
     format: String,
     options: serde_json::Value,
-) -> Result<String, String> {
-    println!("Streaming export to: {}", output_path);
-    
-    // Load connection details
-    let store = app.store_builder("connections.json").build()
-        .map_err(|e| format!("Failed to build store: {}", e))?;
-    
-    let connections: Vec<DatabaseConnection> = store.get("connections")
-        .and_then(|value| serde_json::from_value(value).ok())
-        .unwrap_or_default();
-    
-    let connection = connections.iter()
-        .find(|c| c.id == connection_id)
-        .ok_or("Connection not found")?;
-    
-    // Decrypt password if needed
-    let password = match &connection.password {
-        Some(encrypted) if encryption::is_encrypted(encrypted) => {
-            encryption::decrypt_password(encrypted)?
-        },
-        Some(plain) => plain.clone(),
-        None => String::new(),
-    };
-    
-    let ssl_mode = if connection.ssl.unwrap_or(false) { "require" } else { "disable" };
-    
-    let config = format!(
-        "host={} port={} dbname={} user={} password={} sslmode={}",
-        connection.host,
-        connection.port,
-        connection.database,
-        connection.username,
-        password,
-        ssl_mode
-    );
-    
-    // Connect to database
-    let (client, conn) = tokio_postgres::connect(&config, tokio_postgres::NoTls).await
-        .map_err(|e| format!("Connection failed: {}", e))?;
+    query_id: Option<String>,
+) -> Result<String, AppError> {
+    log::info!("Streaming export to: {}", output_path);
     
-    tokio::spawn(async move {
-        if let Err(e) = conn.await {
-            eprintln!("Connection error: {}", e);
-        }
-    });
+    let connection = connection::resolve_connection(&app, &connection_id)?;
+    safety::check_unconfirmed(&connection, &sql)?;
+    let client = session::DbClient::Pooled(pools.get(&connection).await?);
+    let (query, _notices) = start_query(&app, &queries, query_id, &connection_id, &client);
     
     // Execute query and stream results to file
     let rows = client.query(&sql, &[]).await
-        .map_err(|e| format!("Query execution failed: {}", e))?;
+        .map_err(|e| query.query_error("Query execution failed", e))?;
     
     // Create output file
     let file = File::create(&output_path)
-        .map_err(|e| format!("Failed to create file: {}", e))?;
+        .map_err(|e| AppError::Io(format!("Failed to create file: {}", e)))?;
     let mut writer = BufWriter::new(file);
     
     let include_headers = options.get("includeHeaders")
@@ -655,27 +1054,16 @@ async fn export_query_stream(
                 .map(|col| col.name().to_string())
                 .collect();
             writeln!(writer, "{}", headers.join(","))
-                .map_err(|e| format!("Failed to write headers: {}", e))?;
+                .map_err(|e| AppError::Io(format!("Failed to write headers: {}", e)))?;
         }
         
         // Write rows
         for row in &rows {
             let mut values = Vec::new();
-            for (i, _column) in row.columns().iter().enumerate() {
-                let value_str = if let Ok(v) = row.try_get::<_, Option<String>>(i) {
-                    v.unwrap_or_else(|| "NULL".to_string())
-                } else if let Ok(v) = row.try_get::<_, Option<i32>>(i) {
-                    v.map(|n| n.to_string()).unwrap_or_else(|| "NULL".to_string())
-                } else if let Ok(v) = row.try_get::<_, Option<i64>>(i) {
-                    v.map(|n| n.to_string()).unwrap_or_else(|| "NULL".to_string())
-                } else if let Ok(v) = row.try_get::<_, Option<f64>>(i) {
-                    v.map(|n| n.to_string()).unwrap_or_else(|| "NULL".to_string())
-                } else if let Ok(v) = row.try_get::<_, Option<bool>>(i) {
-                    v.map(|b| b.to_string()).unwrap_or_else(|| "NULL".to_string())
-                } else {
-                    "NULL".to_string()
-                };
-                
+            for i in 0..row.columns().len() {
+                let value = decode::column_to_json(row, i);
+                let value_str = decode::json_to_text(&value).unwrap_or_else(|| "NULL".to_string());
+
                 // Quote value if needed
                 if quote_all || value_str.contains(',') || value_str.contains('"') || value_str.contains('\n') {
                     values.push(format!("\"{}\"", value_str.replace("\"", "\"\"")));
@@ -684,100 +1072,43 @@ async fn export_query_stream(
                 }
             }
             writeln!(writer, "{}", values.join(","))
-                .map_err(|e| format!("Failed to write row: {}", e))?;
+                .map_err(|e| AppError::Io(format!("Failed to write row: {}", e)))?;
         }
     } else if format == "json" {
         // Write JSON
-        let mut json_rows = Vec::new();
-        for row in &rows {
-            let mut row_map = serde_json::Map::new();
-            for (i, column) in row.columns().iter().enumerate() {
-                let column_name = column.name();
-                let value = if let Ok(v) = row.try_get::<_, Option<bool>>(i) {
-                    v.map(serde_json::Value::Bool).unwrap_or(serde_json::Value::Null)
-                } else if let Ok(v) = row.try_get::<_, Option<i32>>(i) {
-                    v.map(|n| serde_json::Value::Number(n.into())).unwrap_or(serde_json::Value::Null)
-                } else if let Ok(v) = row.try_get::<_, Option<i64>>(i) {
-                    v.map(|n| serde_json::Value::Number(n.into())).unwrap_or(serde_json::Value::Null)
-                } else if let Ok(v) = row.try_get::<_, Option<f64>>(i) {
-                    v.and_then(|n| serde_json::Number::from_f64(n))
-                     .map(serde_json::Value::Number)
-                     .unwrap_or(serde_json::Value::Null)
-                } else if let Ok(v) = row.try_get::<_, Option<String>>(i) {
-                    v.map(serde_json::Value::String).unwrap_or(serde_json::Value::Null)
-                } else {
-                    serde_json::Value::Null
-                };
-                row_map.insert(column_name.to_string(), value);
-            }
-            json_rows.push(serde_json::Value::Object(row_map));
-        }
+        let json_rows: Vec<serde_json::Value> = rows.iter().map(decode::row_to_json).collect();
         
         let json_str = serde_json::to_string_pretty(&json_rows)
-            .map_err(|e| format!("Failed to serialize JSON: {}", e))?;
+            .map_err(|e| AppError::Io(format!("Failed to serialize JSON: {}", e)))?;
         writer.write_all(json_str.as_bytes())
-            .map_err(|e| format!("Failed to write JSON: {}", e))?;
+            .map_err(|e| AppError::Io(format!("Failed to write JSON: {}", e)))?;
     }
     
     writer.flush()
-        .map_err(|e| format!("Failed to flush file: {}", e))?;
+        .map_err(|e| AppError::Io(format!("Failed to flush file: {}", e)))?;
     
     Ok(format!("Exported {} rows to {}", rows.len(), output_path))
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn export_query_native(
     app: tauri::AppHandle,
+    pools: tauri::State<'_, pool::PoolManager>,
+    queries: tauri::State<'_, cancel::RunningQueries>,
     connection_id: String,
     sql: String,
     output_path: String,
     format: String,
     include_headers: bool,
-) -> Result<String, String> {
-    println!("Native COPY TO export to: {}", output_path);
-    
-    // Load connection details
-    let store = app.store_builder("connections.json").build()
-        .map_err(|e| format!("Failed to build store: {}", e))?;
+    query_id: Option<String>,
+) -> Result<String, AppError> {
+    log::info!("Native COPY TO export to: {}", output_path);
     
-    let connections: Vec<DatabaseConnection> = store.get("connections")
-        .and_then(|value| serde_json::from_value(value).ok())
-        .unwrap_or_default();
-    
-    let connection = connections.iter()
-        .find(|c| c.id == connection_id)
-        .ok_or("Connection not found")?;
-    
-    // Decrypt password if needed
-    let password = match &connection.password {
-        Some(encrypted) if encryption::is_encrypted(encrypted) => {
-            encryption::decrypt_password(encrypted)?
-        },
-        Some(plain) => plain.clone(),
-        None => String::new(),
-    };
-    
-    let ssl_mode = if connection.ssl.unwrap_or(false) { "require" } else { "disable" };
-    
-    let config = format!(
-        "host={} port={} dbname={} user={} password={} sslmode={}",
-        connection.host,
-        connection.port,
-        connection.database,
-        connection.username,
-        password,
-        ssl_mode
-    );
-    
-    // Connect to database
-    let (client, conn) = tokio_postgres::connect(&config, tokio_postgres::NoTls).await
-        .map_err(|e| format!("Connection failed: {}", e))?;
-    
-    tokio::spawn(async move {
-        if let Err(e) = conn.await {
-            eprintln!("Connection error: {}", e);
-        }
-    });
+    let connection = connection::resolve_connection(&app, &connection_id)?;
+    safety::check_unconfirmed(&connection, &sql)?;
+    let client = session::DbClient::Pooled(pools.get(&connection).await?);
+    let (query, _notices) = start_query(&app, &queries, query_id, &connection_id, &client);
     
     // Build COPY TO command
     let copy_sql = if include_headers {
@@ -788,10 +1119,10 @@ async fn export_query_native(
     
     // Execute COPY TO and write to file
     let copy_reader = client.copy_out(&copy_sql).await
-        .map_err(|e| format!("COPY TO failed: {}", e))?;
+        .map_err(|e| query.query_error("COPY TO failed", e))?;
     
     let file = File::create(&output_path)
-        .map_err(|e| format!("Failed to create file: {}", e))?;
+        .map_err(|e| AppError::Io(format!("Failed to create file: {}", e)))?;
     let mut writer = BufWriter::new(file);
     
     // Read all data from COPY
@@ -804,56 +1135,96 @@ async fn export_query_native(
     
     while let Some(chunk_result) = copy_reader.next().await {
         let chunk = chunk_result
-            .map_err(|e| format!("Failed to read COPY data: {}", e))?;
+            .map_err(|e| query.query_error("Failed to read COPY data", e))?;
         writer.write_all(&chunk)
-            .map_err(|e| format!("Failed to write to file: {}", e))?;
+            .map_err(|e| AppError::Io(format!("Failed to write to file: {}", e)))?;
         total_bytes += chunk.len();
     }
     
     writer.flush()
-        .map_err(|e| format!("Failed to flush file: {}", e))?;
+        .map_err(|e| AppError::Io(format!("Failed to flush file: {}", e)))?;
     
     Ok(format!("Exported {} bytes to {}", total_bytes, output_path))
 }
 
 #[tauri::command]
-async fn connect_to_database(connection: DatabaseConnection) -> Result<(), String> {
-    let password = match &connection.password {
-        Some(encrypted) if encryption::is_encrypted(encrypted) => {
-            encryption::decrypt_password(encrypted)?
-        },
-        Some(plain) => plain.clone(),
-        None => String::new(),
-    };
-    let ssl_mode = if connection.ssl.unwrap_or(false) { "require" } else { "disable" };
-    
-    let config = format!(
-        "host={} port={} dbname={} user={} password={} sslmode={}",
-        connection.host,
-        connection.port,
-        connection.database,
-        connection.username,
-        password,
-        ssl_mode
-    );
-    
-    match tokio_postgres::connect(&config, tokio_postgres::NoTls).await {
-        Ok(_) => {
-            let mut active = ACTIVE_CONNECTION.lock().unwrap();
-            *active = Some(connection.id);
-            Ok(())
-        },
-        Err(e) => Err(e.to_string())
-    }
+async fn connect_to_database(
+    app: tauri::AppHandle,
+    sessions: tauri::State<'_, session::SessionManager>,
+    cursors: tauri::State<'_, paging::ResultCursors>,
+    connection_id: String,
+) -> Result<(), AppError> {
+    let connection = connection::resolve_connection(&app, &connection_id)?;
+    sessions.open(&app, &connection).await?;
+    cursors.drop_session_results();
+    Ok(())
 }
 
 #[tauri::command]
-async fn disconnect_from_database() -> Result<(), String> {
-    let mut active = ACTIVE_CONNECTION.lock().unwrap();
-    *active = None;
+async fn disconnect_from_database(
+    sessions: tauri::State<'_, session::SessionManager>,
+    cursors: tauri::State<'_, paging::ResultCursors>,
+    force: Option<bool>,
+) -> Result<(), AppError> {
+    // Disconnecting silently rolls back an open transaction, so make the caller confirm first
+    if !force.unwrap_or(false) {
+        if let Some(session) = sessions.active() {
+            if session.transaction_status() != session::TransactionStatus::Idle {
+                return Err(AppError::Transaction("A transaction is still open. Commit or roll back before disconnecting.".to_string()));
+            }
+        }
+    }
+
+    sessions.close();
+    cursors.drop_session_results();
     Ok(())
 }
 
+fn active_session(sessions: &session::SessionManager) -> Result<std::sync::Arc<session::Session>, AppError> {
+    sessions.active().ok_or_else(|| AppError::Connection("Not connected to a database".to_string()))
+}
+
+#[tauri::command]
+async fn begin_transaction(
+    sessions: tauri::State<'_, session::SessionManager>,
+    isolation_level: Option<session::IsolationLevel>,
+    read_only: Option<bool>,
+) -> Result<(), AppError> {
+    active_session(&sessions)?.begin(isolation_level, read_only.unwrap_or(false)).await
+}
+
+#[tauri::command]
+async fn commit_transaction(sessions: tauri::State<'_, session::SessionManager>) -> Result<(), AppError> {
+    active_session(&sessions)?.commit().await
+}
+
+#[tauri::command]
+async fn rollback_transaction(sessions: tauri::State<'_, session::SessionManager>) -> Result<(), AppError> {
+    active_session(&sessions)?.rollback().await
+}
+
+#[tauri::command]
+async fn create_savepoint(sessions: tauri::State<'_, session::SessionManager>, name: String) -> Result<(), AppError> {
+    active_session(&sessions)?.savepoint(&name).await
+}
+
+#[tauri::command]
+async fn rollback_to_savepoint(sessions: tauri::State<'_, session::SessionManager>, name: String) -> Result<(), AppError> {
+    active_session(&sessions)?.rollback_to_savepoint(&name).await
+}
+
+#[tauri::command]
+async fn release_savepoint(sessions: tauri::State<'_, session::SessionManager>, name: String) -> Result<(), AppError> {
+    active_session(&sessions)?.release_savepoint(&name).await
+}
+
+#[tauri::command]
+async fn get_transaction_status(sessions: tauri::State<'_, session::SessionManager>) -> Result<session::TransactionStatus, AppError> {
+    Ok(sessions.active()
+        .map(|session| session.transaction_status())
+        .unwrap_or(session::TransactionStatus::Idle))
+}
+
 #[derive(Debug, Serialize)]
 struct SchemaTable {
     table_name: String,
@@ -966,52 +1337,10 @@ struct DatabaseSchema {
 }
 
 #[tauri::command]
-async fn get_database_schema(app: tauri::AppHandle, connection_id: String) -> Result<DatabaseSchema, String> {
-    println!("Fetching schema for connection: {}", connection_id);
-    
-    // Load connections from store to get the connection details
-    let store = app.store_builder("connections.json").build()
-        .map_err(|e| format!("Failed to build store: {}", e))?;
-    
-    let connections: Vec<DatabaseConnection> = store.get("connections")
-        .and_then(|value| serde_json::from_value(value).ok())
-        .unwrap_or_default();
-    
-    let connection = connections.iter()
-        .find(|c| c.id == connection_id)
-        .ok_or("Connection not found")?;
-    
-    // Decrypt password if it's encrypted
-    let password = match &connection.password {
-        Some(encrypted) if encryption::is_encrypted(encrypted) => {
-            encryption::decrypt_password(encrypted)?
-        },
-        Some(plain) => plain.clone(),
-        None => String::new(),
-    };
+async fn get_database_schema(app: tauri::AppHandle, sessions: tauri::State<'_, session::SessionManager>, pools: tauri::State<'_, pool::PoolManager>, connection_id: String) -> Result<DatabaseSchema, AppError> {
+    log::info!("Fetching schema for connection: {}", connection_id);
     
-    let ssl_mode = if connection.ssl.unwrap_or(false) { "require" } else { "disable" };
-    
-    let config = format!(
-        "host={} port={} dbname={} user={} password={} sslmode={}",
-        connection.host,
-        connection.port,
-        connection.database,
-        connection.username,
-        password,
-        ssl_mode
-    );
-    
-    // Connect to database
-    let (client, conn) = tokio_postgres::connect(&config, tokio_postgres::NoTls).await
-        .map_err(|e| format!("Connection failed: {}", e))?;
-    
-    // Spawn connection handler
-    tokio::spawn(async move {
-        if let Err(e) = conn.await {
-            eprintln!("Connection error: {}", e);
-        }
-    });
+    let client = acquire_client(&app, &sessions, &pools, &connection_id).await?;
     
     // Initialize collections for all entity types
     let mut tables = Vec::new();
@@ -1042,7 +1371,7 @@ async fn get_database_schema(app: tauri::AppHandle, connection_id: String) -> Re
     ";
 
     let rows = client.query(table_query, &[]).await
-        .map_err(|e| format!("Schema query failed: {}", e))?;
+        .map_err(|e| AppError::query("Schema query failed", e))?;
 
     for row in rows {
         let table_name: String = row.get(0);
@@ -1077,7 +1406,7 @@ async fn get_database_schema(app: tauri::AppHandle, connection_id: String) -> Re
     ";
     
     let rows = client.query(index_query, &[]).await
-        .map_err(|e| format!("Index query failed: {}", e))?;
+        .map_err(|e| AppError::query("Index query failed", e))?;
     
     for row in rows {
         let index_name: String = row.get(0);
@@ -1112,7 +1441,7 @@ async fn get_database_schema(app: tauri::AppHandle, connection_id: String) -> Re
     ";
     
     let rows = client.query(function_query, &[]).await
-        .map_err(|e| format!("Function query failed: {}", e))?;
+        .map_err(|e| AppError::query("Function query failed", e))?;
     
     for row in rows {
         let function_name: String = row.get(0);
@@ -1140,7 +1469,7 @@ async fn get_database_schema(app: tauri::AppHandle, connection_id: String) -> Re
     ";
 
     let rows = client.query(schemas_query, &[]).await
-        .map_err(|e| format!("Schemas query failed: {}", e))?;
+        .map_err(|e| AppError::query("Schemas query failed", e))?;
 
     for row in rows {
         let schema_name: String = row.get(0);
@@ -1168,52 +1497,10 @@ async fn get_database_schema(app: tauri::AppHandle, connection_id: String) -> Re
 }
 
 #[tauri::command]
-async fn get_table_columns(app: tauri::AppHandle, connection_id: String, table_name: String) -> Result<Vec<SchemaColumn>, String> {
-    println!("Fetching columns for table: {} on connection: {}", table_name, connection_id);
+async fn get_table_columns(app: tauri::AppHandle, sessions: tauri::State<'_, session::SessionManager>, pools: tauri::State<'_, pool::PoolManager>, connection_id: String, table_name: String) -> Result<Vec<SchemaColumn>, AppError> {
+    log::info!("Fetching columns for table: {} on connection: {}", table_name, connection_id);
     
-    // Load connections from store to get the connection details
-    let store = app.store_builder("connections.json").build()
-        .map_err(|e| format!("Failed to build store: {}", e))?;
-    
-    let connections: Vec<DatabaseConnection> = store.get("connections")
-        .and_then(|value| serde_json::from_value(value).ok())
-        .unwrap_or_default();
-    
-    let connection = connections.iter()
-        .find(|c| c.id == connection_id)
-        .ok_or("Connection not found")?;
-    
-    // Decrypt password if it's encrypted
-    let password = match &connection.password {
-        Some(encrypted) if encryption::is_encrypted(encrypted) => {
-            encryption::decrypt_password(encrypted)?
-        },
-        Some(plain) => plain.clone(),
-        None => String::new(),
-    };
-    
-    let ssl_mode = if connection.ssl.unwrap_or(false) { "require" } else { "disable" };
-    
-    let config = format!(
-        "host={} port={} dbname={} user={} password={} sslmode={}",
-        connection.host,
-        connection.port,
-        connection.database,
-        connection.username,
-        password,
-        ssl_mode
-    );
-    
-    // Connect to database
-    let (client, conn) = tokio_postgres::connect(&config, tokio_postgres::NoTls).await
-        .map_err(|e| format!("Connection failed: {}", e))?;
-    
-    // Spawn connection handler
-    tokio::spawn(async move {
-        if let Err(e) = conn.await {
-            eprintln!("Connection error: {}", e);
-        }
-    });
+    let client = acquire_client(&app, &sessions, &pools, &connection_id).await?;
     
     // Query for table columns with primary key information
     let column_query = "
@@ -1240,7 +1527,7 @@ async fn get_table_columns(app: tauri::AppHandle, connection_id: String, table_n
     ";
     
     let rows = client.query(column_query, &[&table_name]).await
-        .map_err(|e| format!("Column query failed: {}", e))?;
+        .map_err(|e| AppError::query("Column query failed", e))?;
     
     let mut columns = Vec::new();
     
@@ -1264,49 +1551,8 @@ async fn get_table_columns(app: tauri::AppHandle, connection_id: String, table_n
 }
 
 #[tauri::command]
-async fn get_table_create_statement(app: tauri::AppHandle, connection_id: String, table_name: String, schema_name: Option<String>) -> Result<String, String> {
-    // Load connections from store to get the connection details
-    let store = app.store_builder("connections.json").build()
-        .map_err(|e| format!("Failed to build store: {}", e))?;
-
-    let connections: Vec<DatabaseConnection> = store.get("connections")
-        .and_then(|value| serde_json::from_value(value).ok())
-        .unwrap_or_default();
-
-    let connection = connections.iter()
-        .find(|c| c.id == connection_id)
-        .ok_or("Connection not found")?;
-
-    // Decrypt password if it's encrypted
-    let password = match &connection.password {
-        Some(encrypted) if encryption::is_encrypted(encrypted) => {
-            encryption::decrypt_password(encrypted)?
-        },
-        Some(plain) => plain.clone(),
-        None => String::new(),
-    };
-
-    let ssl_mode = if connection.ssl.unwrap_or(false) { "require" } else { "disable" };
-
-    let config = format!(
-        "host={} port={} dbname={} user={} password={} sslmode={}",
-        connection.host,
-        connection.port,
-        connection.database,
-        connection.username,
-        password,
-        ssl_mode
-    );
-
-    let (client, conn) = tokio_postgres::connect(&config, tokio_postgres::NoTls).await
-        .map_err(|e| format!("Connection failed: {}", e))?;
-
-    // Spawn connection handler
-    tokio::spawn(async move {
-        if let Err(e) = conn.await {
-            eprintln!("Connection error: {}", e);
-        }
-    });
+async fn get_table_create_statement(app: tauri::AppHandle, sessions: tauri::State<'_, session::SessionManager>, pools: tauri::State<'_, pool::PoolManager>, connection_id: String, table_name: String, schema_name: Option<String>) -> Result<String, AppError> {
+    let client = acquire_client(&app, &sessions, &pools, &connection_id).await?;
 
     let schema_prefix = schema_name.as_ref().map(|s| format!("{}.", s)).unwrap_or_else(|| "public.".to_string());
 
@@ -1329,10 +1575,10 @@ async fn get_table_create_statement(app: tauri::AppHandle, connection_id: String
     ";
 
     let rows = client.query(column_query, &[&table_name, &schema_name.as_ref().unwrap_or(&"public".to_string())]).await
-        .map_err(|e| format!("Column query failed: {}", e))?;
+        .map_err(|e| AppError::query("Column query failed", e))?;
 
     if rows.is_empty() {
-        return Err(format!("Table '{}' not found", table_name));
+        return Err(AppError::NotFound(format!("Table '{}' not found", table_name)));
     }
 
     let mut column_definitions = Vec::new();
@@ -1474,7 +1720,7 @@ async fn get_table_create_statement(app: tauri::AppHandle, connection_id: String
     ";
 
     let constraint_rows = client.query(constraint_query, &[&table_name, &schema_name.as_ref().unwrap_or(&"public".to_string())]).await
-        .map_err(|e| format!("Constraint query failed: {}", e))?;
+        .map_err(|e| AppError::query("Constraint query failed", e))?;
 
     let mut constraints = Vec::new();
 
@@ -1527,16 +1773,9 @@ async fn get_table_create_statement(app: tauri::AppHandle, connection_id: String
 }
 
 #[tauri::command]
-async fn update_last_connected(app: tauri::AppHandle, id: String) -> Result<(), String> {
-    use tauri_plugin_store::StoreExt;
-    
-    let store = app.store_builder("connections.json").build()
-        .map_err(|e| format!("Failed to build store: {}", e))?;
-    
-    let mut connections: Vec<DatabaseConnection> = match store.get("connections") {
-        Some(value) => serde_json::from_value(value.clone()).unwrap_or_default(),
-        None => return Ok(())
-    };
+async fn update_last_connected(app: tauri::AppHandle, id: String) -> Result<(), AppError> {
+    let _store_lock = connection::lock_store();
+    let mut connections = connection::load_connections(&app)?;
     
     for conn in &mut connections {
         if conn.id == id {
@@ -1545,13 +1784,7 @@ async fn update_last_connected(app: tauri::AppHandle, id: String) -> Result<(),
         }
     }
     
-    let value = serde_json::to_value(&connections)
-        .map_err(|e| format!("Failed to serialize connections: {}", e))?;
-    
-    store.set("connections", value);
-    store.save().map_err(|e| format!("Failed to save store: {}", e))?;
-    
-    Ok(())
+    connection::save_connections(&app, &connections)
 }
 
 #[tauri::command]
@@ -1655,10 +1888,24 @@ async fn get_log_path(app: tauri::AppHandle) -> Result<String, String> {
     Ok(log_file.to_string_lossy().to_string())
 }
 
+/// Closing the window or quitting would drop the session and roll back an open
+/// transaction. If one is open, ask the frontend to deal with it first.
+fn close_blocked_by_transaction(app: &tauri::AppHandle) -> bool {
+    let sessions = app.state::<session::SessionManager>();
+    let open_transaction = sessions.active()
+        .is_some_and(|session| session.transaction_status() != session::TransactionStatus::Idle);
+    if open_transaction {
+        if let Err(e) = app.emit("close-blocked-by-transaction", ()) {
+            log::error!("Failed to emit close-blocked-by-transaction: {}", e);
+        }
+    }
+    open_transaction
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
-        .plugin(tauri_plugin_log::Builder::new().build())
+        .plugin(logging::plugin())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_updater::Builder::new().build())
@@ -1668,11 +1915,35 @@ pub fn run() {
         .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_dialog::init())
         .setup(|app| {
+            logging::initialize(app.handle());
             // Initialize encryption
             encryption::initialize_encryption(&app.handle())?;
+            connection::migrate_store(app.handle())?;
             // Migrate existing unencrypted passwords
             encryption::migrate_existing_connections(&app.handle())?;
             
+            // Keep one client pool per stored connection and close idle clients periodically
+            app.manage(pool::PoolManager::new(app.handle()));
+            app.manage(tunnel::TunnelManager::new());
+            app.manage(session::SessionManager::new());
+            app.manage(cancel::RunningQueries::new());
+            app.manage(paging::ResultCursors::new());
+            app.manage(safety::PendingConfirmations::new());
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(pool::REAP_INTERVAL);
+                loop {
+                    interval.tick().await;
+                    app_handle.state::<pool::PoolManager>().reap_idle();
+                    if encryption::lock_if_idle() {
+                        if let Err(e) = app_handle.emit("connections-locked", ()) {
+                            log::error!("Failed to emit connections-locked: {}", e);
+                        }
+                    }
+                    app_handle.state::<paging::ResultCursors>().close_idle().await;
+                }
+            });
+            
             // Create menu items
             let about = MenuItemBuilder::new("About QueryOwl").id("about").build(app)?;
             let quit = MenuItemBuilder::new("Quit QueryOwl")
@@ -1687,6 +1958,10 @@ pub fn run() {
                 .id("show_log_path")
                 .accelerator("CmdOrCtrl+Shift+P")
                 .build(app)?;
+            let full_queries = CheckMenuItemBuilder::new("Log Full Queries")
+                .id("log_full_queries")
+                .checked(logging::full_query_logging())
+                .build(app)?;
             
             // Create Edit menu using Tauri's predefined items
             let edit_submenu = SubmenuBuilder::new(app, "Edit")
@@ -1701,6 +1976,8 @@ pub fn run() {
                 
             let debug_submenu = SubmenuBuilder::new(app, "Debug")
                 .items(&[&open_logs, &show_path])
+                .separator()
+                .item(&full_queries)
                 .build()?;
                 
             let app_submenu = SubmenuBuilder::new(app, "QueryOwl")
@@ -1721,6 +1998,19 @@ pub fn run() {
             get_log_path,
             get_stored_connections,
             save_connection,
+            parse_connection_uri,
+            preview_connection_import,
+            import_connections,
+            export_connections,
+            preview_connection_bundle,
+            import_connection_bundle,
+            get_encryption_status,
+            unlock_connections,
+            lock_connections,
+            set_master_password,
+            remove_master_password,
+            set_auto_lock,
+            rotate_master_key,
             update_connection,
             delete_connection,
             test_database_connection,
@@ -1728,8 +2018,18 @@ pub fn run() {
             create_database,
             list_databases,
             execute_query,
+            cancel_query,
+            fetch_next_page,
+            close_result,
             connect_to_database,
             disconnect_from_database,
+            begin_transaction,
+            commit_transaction,
+            rollback_transaction,
+            create_savepoint,
+            rollback_to_savepoint,
+            release_savepoint,
+            get_transaction_status,
             update_last_connected,
             get_database_schema,
             get_table_columns,
@@ -1739,6 +2039,13 @@ pub fn run() {
             export_query_stream,
             export_query_native
         ])
+        .on_window_event(|window, event| {
+            if let tauri::WindowEvent::CloseRequested { api, .. } = event {
+                if close_blocked_by_transaction(window.app_handle()) {
+                    api.prevent_close();
+                }
+            }
+        })
         .on_menu_event(|app, event| {
             match event.id().as_ref() {
                 "open_log_folder" => {
@@ -1752,21 +2059,26 @@ pub fn run() {
                     tauri::async_runtime::spawn(async move {
                         match get_log_path(app_handle.clone()).await {
                             Ok(path) => {
-                                println!("Emitting log path: {}", path);
+                                log::debug!("Emitting log path: {}", path);
                                 if let Some(window) = app_handle.get_webview_window("main") {
                                     if let Err(e) = window.emit("show_log_path", &path) {
-                                        eprintln!("Failed to emit show_log_path: {}", e);
+                                        log::error!("Failed to emit show_log_path: {}", e);
                                     }
                                 } else {
-                                    eprintln!("No main window found");
+                                    log::error!("No main window found");
                                 }
                             },
                             Err(e) => {
-                                eprintln!("Failed to get log path: {}", e);
+                                log::error!("Failed to get log path: {}", e);
                             }
                         }
                     });
                 },
+                "log_full_queries" => {
+                    if let Err(e) = logging::set_full_query_logging(app, !logging::full_query_logging()) {
+                        log::error!("Failed to change full query logging: {}", e);
+                    }
+                },
                 "about" => {
                     #[cfg(target_os = "macos")]
                     {
@@ -1785,7 +2097,9 @@ A powerful PostgreSQL database query tool built with Tauri and Svelte 5
                     }
                 },
                 "quit" => {
-                    app.exit(0);
+                    if !close_blocked_by_transaction(app) {
+                        app.exit(0);
+                    }
                 },
                 _ => {}
             }
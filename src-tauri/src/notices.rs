@@ -0,0 +1,191 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tauri::Emitter;
+use tokio_postgres::error::DbError;
+
+/// A NOTICE, WARNING or other non-error message sent by the server, e.g. `RAISE NOTICE` output.
+#[derive(Debug, Clone, Serialize)]
+pub struct Notice {
+    pub severity: String,
+    pub code: String,
+    pub message: String,
+    pub detail: Option<String>,
+    pub hint: Option<String>,
+}
+
+impl From<&DbError> for Notice {
+    fn from(e: &DbError) -> Self {
+        Notice {
+            severity: e.severity().to_string(),
+            code: e.code().code().to_string(),
+            message: e.message().to_string(),
+            detail: e.detail().map(str::to_string),
+            hint: e.hint().map(str::to_string),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct QueryNoticePayload<'a> {
+    connection_id: &'a str,
+    query_id: &'a str,
+    notice: &'a Notice,
+}
+
+// Notices by the query they arrived during. Commands can overlap on the live
+// session, so each keeps its own until its scope ends.
+#[derive(Default)]
+struct NoticeState {
+    active: Option<String>,
+    by_query: HashMap<String, Vec<Notice>>,
+}
+
+impl NoticeState {
+    fn start(&mut self, query_id: &str) {
+        self.active = Some(query_id.to_string());
+        self.by_query.insert(query_id.to_string(), Vec::new());
+    }
+
+    fn finish(&mut self, query_id: &str) {
+        self.by_query.remove(query_id);
+        if self.active.as_deref() == Some(query_id) {
+            self.active = None;
+        }
+    }
+
+    fn take(&mut self, query_id: &str) -> Vec<Notice> {
+        self.by_query.get_mut(query_id).map(std::mem::take).unwrap_or_default()
+    }
+
+    // The query the notice was attributed to, if any
+    fn push(&mut self, notice: Notice) -> Option<&str> {
+        let query_id = self.active.as_deref()?;
+        self.by_query.get_mut(query_id)?.push(notice);
+        Some(query_id)
+    }
+}
+
+/// Notices received on one client's connection. The driver task pushes them in;
+/// the command running a query collects the ones that arrived while it ran.
+#[derive(Clone)]
+pub struct Notices(Arc<NoticesInner>);
+
+struct NoticesInner {
+    app: tauri::AppHandle,
+    connection_id: String,
+    state: Mutex<NoticeState>,
+}
+
+impl Notices {
+    pub fn new(app: &tauri::AppHandle, connection_id: &str) -> Self {
+        Notices(Arc::new(NoticesInner {
+            app: app.clone(),
+            connection_id: connection_id.to_string(),
+            state: Mutex::new(NoticeState::default()),
+        }))
+    }
+
+    /// Attribute notices that arrive from now on to this query, until another
+    /// query starts or the returned scope is dropped.
+    pub fn start(&self, query_id: &str) -> NoticeScope {
+        self.0.state.lock().unwrap().start(query_id);
+        NoticeScope {
+            notices: self.clone(),
+            query_id: query_id.to_string(),
+        }
+    }
+
+    /// Stop collecting for every query, e.g. before a pooled client is reset for the next user.
+    pub fn clear(&self) {
+        *self.0.state.lock().unwrap() = NoticeState::default();
+    }
+
+    /// Called by the driver task. Notices outside a query (such as the reset of
+    /// a pooled client) are dropped; the rest are buffered and emitted right away
+    /// as `query-notice` so output of long-running procedures shows up live.
+    pub fn push(&self, notice: Notice) {
+        let mut state = self.0.state.lock().unwrap();
+        let payload_notice = notice.clone();
+        let Some(query_id) = state.push(notice) else { return };
+
+        let payload = QueryNoticePayload {
+            connection_id: &self.0.connection_id,
+            query_id,
+            notice: &payload_notice,
+        };
+        if let Err(e) = self.0.app.emit("query-notice", payload) {
+            log::error!("Failed to emit query-notice: {}", e);
+        }
+    }
+}
+
+/// Collects a query's notices while it runs. Dropping it, however the command
+/// ends, stops attributing notices to the query; the live session otherwise
+/// keeps tagging later ones, like those of schema queries, with its id.
+pub struct NoticeScope {
+    notices: Notices,
+    query_id: String,
+}
+
+impl NoticeScope {
+    /// The query's notices collected since the last call.
+    pub fn take(&self) -> Vec<Notice> {
+        self.notices.0.state.lock().unwrap().take(&self.query_id)
+    }
+}
+
+impl Drop for NoticeScope {
+    fn drop(&mut self) {
+        // A query started since then keeps its notices
+        self.notices.0.state.lock().unwrap().finish(&self.query_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notice(message: &str) -> Notice {
+        Notice {
+            severity: "NOTICE".to_string(),
+            code: "00000".to_string(),
+            message: message.to_string(),
+            detail: None,
+            hint: None,
+        }
+    }
+
+    fn messages(notices: Vec<Notice>) -> Vec<String> {
+        notices.into_iter().map(|notice| notice.message).collect()
+    }
+
+    #[test]
+    fn drops_notices_outside_a_query() {
+        let mut state = NoticeState::default();
+        assert!(state.push(notice("reset")).is_none());
+        state.start("a");
+        state.finish("a");
+        assert!(state.push(notice("schema query")).is_none());
+        assert!(state.take("a").is_empty());
+    }
+
+    #[test]
+    fn overlapping_queries_keep_their_own_notices() {
+        let mut state = NoticeState::default();
+        state.start("a");
+        assert_eq!(state.push(notice("first")), Some("a"));
+
+        state.start("b");
+        assert_eq!(state.push(notice("second")), Some("b"));
+
+        // The first query ending doesn't stop attributing to the second
+        assert_eq!(messages(state.take("a")), vec!["first"]);
+        state.finish("a");
+        assert_eq!(state.push(notice("third")), Some("b"));
+        assert_eq!(messages(state.take("b")), vec!["second", "third"]);
+
+        state.finish("b");
+        assert!(state.push(notice("after")).is_none());
+    }
+}
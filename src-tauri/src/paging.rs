@@ -0,0 +1,344 @@
+use futures::StreamExt;
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio_postgres::{Row, RowStream};
+use uuid::Uuid;
+
+use crate::script;
+use crate::session::DbClient;
+
+/// Rows are handed to the frontend in batches of this size while a page is read.
+pub const BATCH_SIZE: usize = 200;
+// Results nobody fetched from for this long are closed so the server can free them
+const RESULT_IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// A query whose rows have only been partly read. Plain queries are read through
+/// a server-side cursor, so the rest of the result waits on the server and the
+/// client stays free for other commands in between pages. A cursor the live
+/// session opened a transaction for is closed by the next command on the session.
+pub struct OpenResult {
+    pub connection_id: String,
+    /// What the statement runs, as named by `script::command_name`
+    command: String,
+    client: DbClient,
+    source: RowSource,
+    peeked: Option<Row>,
+    last_used: Instant,
+}
+
+enum RowSource {
+    /// Rows of a statement sent with `query_raw`, read to the end in one go
+    Stream(Pin<Box<RowStream>>),
+    Cursor(Cursor),
+}
+
+struct Cursor {
+    name: String,
+    buffered: VecDeque<Row>,
+    fetched: u64,
+    exhausted: bool,
+    /// Opened in a transaction of its own, which ends with it
+    owns_transaction: bool,
+}
+
+pub struct Page {
+    pub row_count: usize,
+    pub has_more: bool,
+}
+
+/// A new, unique cursor name.
+pub fn cursor_name() -> String {
+    format!("queryowl_{}", Uuid::new_v4().simple())
+}
+
+/// The statements opening a cursor over `sql`. A cursor only hands out rows as
+/// they are fetched inside a transaction block; a `WITH HOLD` one would have the
+/// whole result computed and stored when its statement commits. So outside a
+/// transaction the cursor gets one of its own, ended when the cursor is done.
+pub fn open_cursor(name: &str, sql: &str, in_transaction: bool) -> String {
+    let declare = format!("DECLARE {} NO SCROLL CURSOR FOR {}", name, sql);
+    if in_transaction {
+        declare
+    } else {
+        format!("BEGIN; {}", declare)
+    }
+}
+
+impl OpenResult {
+    pub fn new(connection_id: &str, command: String, client: DbClient, stream: RowStream) -> Self {
+        Self::with_source(connection_id, command, client, RowSource::Stream(Box::pin(stream)))
+    }
+
+    /// A result read from a cursor opened with `open_cursor`.
+    pub fn from_cursor(connection_id: &str, client: DbClient, name: String, owns_transaction: bool) -> Self {
+        if owns_transaction {
+            client.hold_result_transaction(&name);
+        }
+        let cursor = Cursor {
+            name,
+            buffered: VecDeque::new(),
+            fetched: 0,
+            exhausted: false,
+            owns_transaction,
+        };
+        Self::with_source(connection_id, "SELECT".to_string(), client, RowSource::Cursor(cursor))
+    }
+
+    fn with_source(connection_id: &str, command: String, client: DbClient, source: RowSource) -> Self {
+        OpenResult {
+            connection_id: connection_id.to_string(),
+            command,
+            client,
+            source,
+            peeked: None,
+            last_used: Instant::now(),
+        }
+    }
+
+    pub fn client(&self) -> &DbClient {
+        &self.client
+    }
+
+    pub fn client_mut(&mut self) -> &mut DbClient {
+        &mut self.client
+    }
+
+    /// The statement's command tag, known once every row has been read.
+    pub fn command_tag(&self) -> Option<String> {
+        self.total_rows().map(|rows| script::command_tag(&self.command, rows))
+    }
+
+    /// How many rows the statement returned, known once every row has been read.
+    pub fn total_rows(&self) -> Option<u64> {
+        if self.peeked.is_some() {
+            return None;
+        }
+        match &self.source {
+            RowSource::Stream(stream) => stream.rows_affected(),
+            RowSource::Cursor(cursor) => cursor.exhausted.then_some(cursor.fetched),
+        }
+    }
+
+    pub fn is_session(&self) -> bool {
+        matches!(self.client, DbClient::Session(_))
+    }
+
+    /// Whether the rest of the result can still be read. A cursor declared
+    /// inside a transaction block is gone once the transaction ends.
+    pub async fn is_open(&self) -> Result<bool, tokio_postgres::Error> {
+        match &self.source {
+            RowSource::Stream(_) => Ok(true),
+            RowSource::Cursor(cursor) if cursor.exhausted => Ok(true),
+            RowSource::Cursor(cursor) => {
+                let row = self.client.query_opt("SELECT 1 FROM pg_cursors WHERE name = $1", &[&cursor.name]).await?;
+                Ok(row.is_some())
+            },
+        }
+    }
+
+    async fn next_row(&mut self) -> Result<Option<Row>, tokio_postgres::Error> {
+        match &mut self.source {
+            RowSource::Stream(stream) => stream.next().await.transpose(),
+            RowSource::Cursor(cursor) => {
+                if cursor.buffered.is_empty() && !cursor.exhausted {
+                    let fetch = format!("FETCH FORWARD {} FROM {}", BATCH_SIZE, cursor.name);
+                    let rows = self.client.query(&fetch, &[]).await?;
+                    cursor.fetched += rows.len() as u64;
+                    if rows.len() < BATCH_SIZE {
+                        finish_cursor(&self.client, cursor).await?;
+                        cursor.exhausted = true;
+                    }
+                    cursor.buffered.extend(rows);
+                }
+                Ok(cursor.buffered.pop_front())
+            },
+        }
+    }
+
+    /// Read up to `page_size` rows, handing them to `on_batch` `BATCH_SIZE` at a time.
+    pub async fn read_page(
+        &mut self,
+        page_size: usize,
+        mut on_batch: impl FnMut(&[Row]),
+    ) -> Result<Page, tokio_postgres::Error> {
+        self.last_used = Instant::now();
+
+        let mut row_count = 0;
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        if let Some(row) = self.peeked.take() {
+            batch.push(row);
+            row_count += 1;
+        }
+
+        while row_count < page_size {
+            match self.next_row().await? {
+                Some(row) => {
+                    batch.push(row);
+                    row_count += 1;
+                },
+                None => break,
+            }
+            if batch.len() >= BATCH_SIZE {
+                on_batch(&batch);
+                batch.clear();
+            }
+        }
+        if !batch.is_empty() {
+            on_batch(&batch);
+        }
+
+        // Look one row ahead so the caller knows whether another page exists
+        if row_count == page_size {
+            self.peeked = self.next_row().await?;
+        }
+
+        Ok(Page {
+            row_count,
+            has_more: self.peeked.is_some(),
+        })
+    }
+
+    /// Read the rows that are left without keeping them, so the statement
+    /// finishes normally and the client is free for the next one.
+    pub async fn discard(&mut self) -> Result<(), tokio_postgres::Error> {
+        self.last_used = Instant::now();
+        self.peeked = None;
+        match &mut self.source {
+            RowSource::Stream(stream) => {
+                while let Some(row) = stream.next().await {
+                    row?;
+                }
+            },
+            RowSource::Cursor(cursor) => {
+                // MOVE skips the rows on the server; its tag gives their count
+                if !cursor.exhausted {
+                    let moved = self.client.execute(&format!("MOVE FORWARD ALL IN {}", cursor.name), &[]).await?;
+                    finish_cursor(&self.client, cursor).await?;
+                    cursor.fetched += moved;
+                    cursor.exhausted = true;
+                }
+                cursor.buffered.clear();
+            },
+        }
+        Ok(())
+    }
+
+    /// Close the result and hand back its client. Rows left on the server are
+    /// thrown away without canceling anything, so an open transaction carries on.
+    pub async fn close(mut self) -> DbClient {
+        match &mut self.source {
+            RowSource::Stream(stream) => {
+                while let Some(Ok(_)) = stream.next().await {}
+            },
+            RowSource::Cursor(cursor) if cursor.exhausted => {},
+            RowSource::Cursor(cursor) if cursor.owns_transaction => {
+                // Nothing to do if another command already ended the transaction
+                if let Err(e) = self.client.end_result_transaction(&cursor.name).await {
+                    log::warn!("Failed to close cursor: {}", e);
+                }
+            },
+            RowSource::Cursor(cursor) => {
+                // The cursor may have gone with its transaction; CLOSE would then fail
+                // and abort whatever transaction the session is in now.
+                let name = cursor.name.clone();
+                match self.is_open().await {
+                    Ok(true) => {
+                        if let Err(e) = self.client.batch_execute(&format!("CLOSE {}", name)).await {
+                            log::warn!("Failed to close cursor: {}", e);
+                        }
+                    },
+                    Ok(false) => {},
+                    Err(e) => log::warn!("Failed to close cursor: {}", e),
+                }
+            },
+        }
+        self.client
+    }
+}
+
+// Close the cursor, or end the transaction that was opened for it
+async fn finish_cursor(client: &DbClient, cursor: &Cursor) -> Result<(), tokio_postgres::Error> {
+    if cursor.owns_transaction {
+        client.end_result_transaction(&cursor.name).await
+    } else {
+        client.batch_execute(&format!("CLOSE {}", cursor.name)).await
+    }
+}
+
+/// Partly-read results kept around for "fetch next page", keyed by result id.
+pub struct ResultCursors {
+    results: Mutex<HashMap<String, OpenResult>>,
+}
+
+impl ResultCursors {
+    pub fn new() -> Self {
+        ResultCursors {
+            results: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn new_result_id() -> String {
+        Uuid::new_v4().to_string()
+    }
+
+    /// Keep a result for later pages. A pooled client waiting on one gives up its
+    /// pool slot, so a few results left open don't starve other commands.
+    pub fn insert(&self, result_id: &str, mut result: OpenResult) {
+        result.client_mut().detach();
+        self.results.lock().unwrap().insert(result_id.to_string(), result);
+    }
+
+    /// Take a result out while a page is read; put it back with `insert`.
+    pub fn take(&self, result_id: &str) -> Option<OpenResult> {
+        self.results.lock().unwrap().remove(result_id)
+    }
+
+    /// Forget the live session's results once it is closed; its cursors went with it.
+    pub fn drop_session_results(&self) {
+        self.remove_where(OpenResult::is_session);
+    }
+
+    pub async fn close_idle(&self) {
+        let idle = self.remove_where(|result| result.last_used.elapsed() > RESULT_IDLE_TIMEOUT);
+        for result in idle {
+            result.close().await;
+        }
+    }
+
+    fn remove_where(&self, predicate: impl Fn(&OpenResult) -> bool) -> Vec<OpenResult> {
+        let mut results = self.results.lock().unwrap();
+        let ids: Vec<String> = results.iter()
+            .filter(|(_, result)| predicate(result))
+            .map(|(id, _)| id.clone())
+            .collect();
+        ids.iter().filter_map(|id| results.remove(id)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_gets_a_transaction_outside_one() {
+        assert_eq!(
+            open_cursor("queryowl_1", "SELECT * FROM t", false),
+            "BEGIN; DECLARE queryowl_1 NO SCROLL CURSOR FOR SELECT * FROM t"
+        );
+        assert_eq!(
+            open_cursor("queryowl_1", "SELECT * FROM t", true),
+            "DECLARE queryowl_1 NO SCROLL CURSOR FOR SELECT * FROM t"
+        );
+        assert!(!open_cursor("queryowl_1", "SELECT 1", false).contains("HOLD"));
+    }
+
+    #[test]
+    fn cursor_names_are_unique_identifiers() {
+        let name = cursor_name();
+        assert_ne!(name, cursor_name());
+        assert!(name.starts_with("queryowl_"));
+        assert!(name.bytes().all(|c| c.is_ascii_alphanumeric() || c == b'_'));
+    }
+}
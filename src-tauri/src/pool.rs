@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_postgres::Client;
+
+use crate::connection;
+use crate::error::AppError;
+use crate::notices::Notices;
+use crate::DatabaseConnection;
+
+const DEFAULT_MAX_SIZE: usize = 4;
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+// Clients that sat idle longer than this get a round trip before being handed out
+const HEALTH_CHECK_AFTER: Duration = Duration::from_secs(30);
+const CHECKOUT_TIMEOUT: Duration = Duration::from_secs(30);
+pub const REAP_INTERVAL: Duration = Duration::from_secs(60);
+
+struct IdleClient {
+    client: Client,
+    notices: Notices,
+    idle_since: Instant,
+}
+
+struct ConnectionPool {
+    idle: Mutex<Vec<IdleClient>>,
+    permits: Arc<Semaphore>,
+    closed: AtomicBool,
+}
+
+impl ConnectionPool {
+    fn new(max_size: usize) -> Self {
+        ConnectionPool {
+            idle: Mutex::new(Vec::new()),
+            permits: Arc::new(Semaphore::new(max_size)),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.idle.lock().unwrap().clear();
+    }
+}
+
+/// Pools of open clients keyed by `DatabaseConnection.id`, held in Tauri state.
+pub struct PoolManager {
+    app: tauri::AppHandle,
+    pools: Mutex<HashMap<String, Arc<ConnectionPool>>>,
+    max_size: usize,
+    idle_timeout: Duration,
+}
+
+impl PoolManager {
+    pub fn new(app: &tauri::AppHandle) -> Self {
+        PoolManager {
+            app: app.clone(),
+            pools: Mutex::new(HashMap::new()),
+            max_size: DEFAULT_MAX_SIZE,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+        }
+    }
+
+    /// Check out a client for the connection, reusing an idle one when possible.
+    pub async fn get(&self, connection: &DatabaseConnection) -> Result<PooledClient, AppError> {
+        let pool = {
+            let mut pools = self.pools.lock().unwrap();
+            pools.entry(connection.id.clone())
+                .or_insert_with(|| Arc::new(ConnectionPool::new(self.max_size)))
+                .clone()
+        };
+
+        let permit = tokio::time::timeout(CHECKOUT_TIMEOUT, pool.permits.clone().acquire_owned()).await
+            .map_err(|_| AppError::Connection("Timed out waiting for a free connection".to_string()))?
+            .map_err(|_| AppError::Connection("Connection pool was closed".to_string()))?;
+
+        loop {
+            let candidate = pool.idle.lock().unwrap().pop();
+            let Some(idle) = candidate else { break };
+
+            if idle.client.is_closed() || idle.idle_since.elapsed() > self.idle_timeout {
+                continue;
+            }
+
+            if idle.idle_since.elapsed() > HEALTH_CHECK_AFTER
+                && idle.client.simple_query("").await.is_err()
+            {
+                continue;
+            }
+
+            return Ok(PooledClient {
+                client: Some(idle.client),
+                notices: idle.notices,
+                pool,
+                permit: Some(permit),
+            });
+        }
+
+        let (client, notices, _driver) = connection::connect(&self.app, connection).await?;
+
+        Ok(PooledClient {
+            client: Some(client),
+            notices,
+            pool,
+            permit: Some(permit),
+        })
+    }
+
+    /// Drop every pooled client for a connection, e.g. after it was edited or deleted.
+    /// Clients that are currently checked out are closed when they are returned.
+    pub fn evict(&self, connection_id: &str) {
+        if let Some(pool) = self.pools.lock().unwrap().remove(connection_id) {
+            pool.close();
+        }
+    }
+
+    /// Close clients that have been idle longer than the idle timeout.
+    pub fn reap_idle(&self) {
+        let pools = self.pools.lock().unwrap();
+        for pool in pools.values() {
+            pool.idle.lock().unwrap().retain(|idle| {
+                !idle.client.is_closed() && idle.idle_since.elapsed() <= self.idle_timeout
+            });
+        }
+    }
+}
+
+/// A client checked out of a pool. It goes back to the pool when dropped.
+pub struct PooledClient {
+    client: Option<Client>,
+    notices: Notices,
+    pool: Arc<ConnectionPool>,
+    permit: Option<OwnedSemaphorePermit>,
+}
+
+impl PooledClient {
+    pub fn notices(&self) -> &Notices {
+        &self.notices
+    }
+
+    /// Give the client's slot back to the pool while keeping the client, e.g. for
+    /// a result that may wait minutes for its next page. It is closed when dropped.
+    pub fn detach(&mut self) {
+        self.permit = None;
+    }
+}
+
+impl Deref for PooledClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        self.client.as_ref().expect("pooled client already released")
+    }
+}
+
+impl Drop for PooledClient {
+    fn drop(&mut self) {
+        let Some(client) = self.client.take() else { return };
+        self.notices.clear();
+        let detached = self.permit.is_none();
+        if detached || client.is_closed() || self.pool.closed.load(Ordering::SeqCst) {
+            return;
+        }
+
+        // Reset the session in the background so an open transaction or a SET
+        // issued by one command doesn't leak into the next one.
+        // The permit is held until then so the pool never exceeds its max size.
+        let pool = self.pool.clone();
+        let notices = self.notices.clone();
+        let permit = self.permit.take();
+        tauri::async_runtime::spawn(async move {
+            if client.simple_query("ROLLBACK; RESET ALL").await.is_ok()
+                && !pool.closed.load(Ordering::SeqCst)
+            {
+                pool.idle.lock().unwrap().push(IdleClient {
+                    client,
+                    notices,
+                    idle_since: Instant::now(),
+                });
+            }
+            drop(permit);
+        });
+    }
+}
@@ -0,0 +1,76 @@
+use serde::Serialize;
+use tokio_postgres::error::ErrorPosition;
+
+/// A failed query, with the server's error fields when there are any so the
+/// frontend can tell error kinds apart by SQLSTATE and point at the failing token.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct QueryError {
+    pub message: String,
+    pub code: Option<String>,
+    pub severity: Option<String>,
+    pub detail: Option<String>,
+    pub hint: Option<String>,
+    /// 1-based character position in the submitted SQL
+    pub position: Option<u32>,
+    /// Position inside an internally generated query, e.g. in a PL/pgSQL function
+    pub internal_position: Option<u32>,
+    pub internal_query: Option<String>,
+    #[serde(rename = "where")]
+    pub where_: Option<String>,
+    pub schema: Option<String>,
+    pub table: Option<String>,
+    pub column: Option<String>,
+    pub datatype: Option<String>,
+    pub constraint: Option<String>,
+}
+
+impl QueryError {
+    pub fn new(message: String, e: &tokio_postgres::Error) -> Self {
+        let Some(db) = e.as_db_error() else {
+            return QueryError { message, ..Default::default() };
+        };
+
+        let (position, internal_position, internal_query) = match db.position() {
+            Some(ErrorPosition::Original(position)) => (Some(*position), None, None),
+            Some(ErrorPosition::Internal { position, query }) => (None, Some(*position), Some(query.clone())),
+            None => (None, None, None),
+        };
+
+        QueryError {
+            message,
+            code: Some(db.code().code().to_string()),
+            severity: Some(db.severity().to_string()),
+            detail: db.detail().map(str::to_string),
+            hint: db.hint().map(str::to_string),
+            position,
+            internal_position,
+            internal_query,
+            where_: db.where_().map(str::to_string),
+            schema: db.schema().map(str::to_string),
+            table: db.table().map(str::to_string),
+            column: db.column().map(str::to_string),
+            datatype: db.datatype().map(str::to_string),
+            constraint: db.constraint().map(str::to_string),
+        }
+    }
+
+    /// Make `position` relative to the whole script when the statement that
+    /// failed starts `offset` characters into it.
+    pub fn offset_position(mut self, offset: u32) -> Self {
+        self.position = self.position.map(|position| position + offset);
+        self
+    }
+
+    /// Make `position` relative to the statement when it was sent wrapped in
+    /// `prefix` more characters, e.g. inside a `DECLARE ... FOR`.
+    pub fn strip_prefix(mut self, prefix: u32) -> Self {
+        self.position = self.position.map(|position| position.saturating_sub(prefix).max(1));
+        self
+    }
+}
+
+impl From<String> for QueryError {
+    fn from(message: String) -> Self {
+        QueryError { message, ..Default::default() }
+    }
+}
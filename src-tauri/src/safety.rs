@@ -0,0 +1,304 @@
+use serde::{Deserialize, Serialize};
+use sqlparser::ast::{AlterTableOperation, FromTable, ObjectName, Query, SetExpr, Statement, TableFactor, TableWithJoins};
+use sqlparser::dialect::PostgreSqlDialect;
+use sqlparser::parser::Parser;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::connection::Environment;
+use crate::error::AppError;
+use crate::script;
+use crate::DatabaseConnection;
+
+/// How long a confirmation token stays valid.
+const CONFIRMATION_TTL: Duration = Duration::from_secs(5 * 60);
+
+// Statements that could be destructive, checked by keyword when the parser gives up
+const DESTRUCTIVE_KEYWORDS: &[&str] = &["ALTER", "DELETE", "DO", "DROP", "MERGE", "TRUNCATE", "UPDATE"];
+// Of those, the ones a statement starting with WITH can run
+const CTE_DESTRUCTIVE_KEYWORDS: &[&str] = &["DELETE", "MERGE", "UPDATE"];
+
+/// What `execute_query` may run on a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SafetyPolicy {
+    /// Sessions start with `default_transaction_read_only` on
+    ReadOnly,
+    /// Destructive statements only run when sent again with a confirmation token
+    ConfirmDestructive,
+    Unrestricted,
+}
+
+impl SafetyPolicy {
+    /// The connection's policy. Production connections without one confirm destructive statements.
+    pub fn of(connection: &DatabaseConnection) -> Self {
+        match (connection.connect_options.safety_policy, connection.labels.environment) {
+            (Some(policy), _) => policy,
+            (None, Some(Environment::Production)) => SafetyPolicy::ConfirmDestructive,
+            (None, _) => SafetyPolicy::Unrestricted,
+        }
+    }
+}
+
+/// A statement that needs confirming, and what it would do.
+#[derive(Debug, Serialize)]
+pub struct DestructiveStatement {
+    pub statement: String,
+    pub description: String,
+}
+
+/// The statements that drop objects, empty tables or change every row of one.
+pub fn destructive_statements(statements: &[&str]) -> Vec<DestructiveStatement> {
+    statements.iter()
+        .flat_map(|statement| {
+            describe(statement).into_iter().map(|description| DestructiveStatement {
+                statement: statement.to_string(),
+                description,
+            })
+        })
+        .collect()
+}
+
+/// For commands without a confirmation step, like exports: refuse destructive
+/// SQL on connections that would ask first.
+pub fn check_unconfirmed(connection: &DatabaseConnection, sql: &str) -> Result<(), AppError> {
+    if SafetyPolicy::of(connection) != SafetyPolicy::ConfirmDestructive {
+        return Ok(());
+    }
+    match destructive_statements(&script::split_statements(sql)).first() {
+        Some(destructive) => Err(AppError::InvalidInput(format!(
+            "Exports can't run destructive statements on this connection: {}",
+            destructive.description
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn describe(sql: &str) -> Vec<String> {
+    let parsed = match Parser::parse_sql(&PostgreSqlDialect {}, sql) {
+        Ok(parsed) => parsed,
+        Err(_) => {
+            // Syntax the parser doesn't know, like DO blocks or DELETE in a
+            // common table expression; err on the side of asking
+            let keywords = script::keywords(sql);
+            let keyword = match keywords.first().map(String::as_str) {
+                Some("WITH") => keywords.iter().find(|keyword| CTE_DESTRUCTIVE_KEYWORDS.contains(&keyword.as_str())),
+                _ => keywords.first().filter(|keyword| DESTRUCTIVE_KEYWORDS.contains(&keyword.as_str())),
+            };
+            return keyword
+                .map(|keyword| vec![format!("Runs a {} statement that could not be checked", keyword)])
+                .unwrap_or_default();
+        },
+    };
+
+    let mut statements = Vec::new();
+    for statement in &parsed {
+        collect_statements(statement, &mut statements);
+    }
+    statements.into_iter().filter_map(describe_statement).collect()
+}
+
+// The statement and the ones its common table expressions and query bodies run,
+// as in `WITH u AS (UPDATE t SET ... RETURNING *) SELECT ...`
+fn collect_statements<'s>(statement: &'s Statement, statements: &mut Vec<&'s Statement>) {
+    statements.push(statement);
+    match statement {
+        Statement::Query(query) => collect_query_statements(query, statements),
+        Statement::Insert(insert) => {
+            if let Some(source) = &insert.source {
+                collect_query_statements(source, statements);
+            }
+        },
+        _ => {},
+    }
+}
+
+fn collect_query_statements<'s>(query: &'s Query, statements: &mut Vec<&'s Statement>) {
+    for cte in query.with.iter().flat_map(|with| &with.cte_tables) {
+        collect_query_statements(&cte.query, statements);
+    }
+    match query.body.as_ref() {
+        SetExpr::Insert(statement) | SetExpr::Update(statement) => collect_statements(statement, statements),
+        SetExpr::Query(query) => collect_query_statements(query, statements),
+        _ => {},
+    }
+}
+
+fn describe_statement(statement: &Statement) -> Option<String> {
+    match statement {
+        Statement::Drop { object_type, names, cascade, .. } => Some(format!(
+            "Drops {} {}{}",
+            object_type.to_string().to_lowercase(),
+            join(names),
+            if *cascade { " and everything that depends on it" } else { "" }
+        )),
+        Statement::DropFunction { func_desc, .. } => Some(format!("Drops function {}", join(func_desc.iter().map(|f| &f.name)))),
+        Statement::DropProcedure { proc_desc, .. } => Some(format!("Drops procedure {}", join(proc_desc.iter().map(|p| &p.name)))),
+        Statement::DropTrigger { trigger_name, table_name, .. } => Some(format!("Drops trigger {} on {}", trigger_name, table_name)),
+        Statement::DropPolicy { name, table_name, .. } => Some(format!("Drops policy {} on {}", name, table_name)),
+        Statement::Truncate { table_names, .. } => Some(format!(
+            "Removes every row from {}",
+            join(table_names.iter().map(|target| &target.name))
+        )),
+        Statement::Delete(delete) if delete.selection.is_none() => {
+            let tables = match &delete.from {
+                FromTable::WithFromKeyword(tables) | FromTable::WithoutKeyword(tables) => tables,
+            };
+            Some(format!("Deletes every row in {}", table_names(tables)))
+        },
+        Statement::Update { table, selection: None, .. } => {
+            Some(format!("Updates every row in {}", table_names(std::slice::from_ref(table))))
+        },
+        Statement::AlterTable { name, operations, .. } => {
+            let dropped = operations.iter()
+                .filter_map(|operation| match operation {
+                    AlterTableOperation::DropColumn { column_name, .. } => Some(format!("column {}", column_name)),
+                    AlterTableOperation::DropConstraint { name, .. } => Some(format!("constraint {}", name)),
+                    AlterTableOperation::DropPrimaryKey => Some("the primary key".to_string()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            (!dropped.is_empty()).then(|| format!("Drops {} from {}", dropped.join(", "), name))
+        },
+        // WHEN MATCHED clauses can update or delete any row of the target
+        Statement::Merge { table, .. } => Some(format!("Merges into {}, which can update or delete its rows", relation_name(table))),
+        _ => None,
+    }
+}
+
+fn join<'n>(names: impl IntoIterator<Item = &'n ObjectName>) -> String {
+    names.into_iter().map(|name| name.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+// Just the table names, without aliases
+fn table_names(tables: &[TableWithJoins]) -> String {
+    tables.iter()
+        .map(|table| relation_name(&table.relation))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn relation_name(relation: &TableFactor) -> String {
+    match relation {
+        TableFactor::Table { name, .. } => name.to_string(),
+        relation => relation.to_string(),
+    }
+}
+
+struct PendingConfirmation {
+    connection_id: String,
+    sql: String,
+    expires_at: Instant,
+}
+
+/// Tokens handed out with a "needs confirmation" response. Each one lets the
+/// exact same SQL run once on the same connection.
+pub struct PendingConfirmations {
+    pending: Mutex<HashMap<String, PendingConfirmation>>,
+}
+
+impl PendingConfirmations {
+    pub fn new() -> Self {
+        PendingConfirmations {
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn issue(&self, connection_id: &str, sql: &str) -> String {
+        let token = Uuid::new_v4().to_string();
+        let now = Instant::now();
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, confirmation| confirmation.expires_at > now);
+        pending.insert(token.clone(), PendingConfirmation {
+            connection_id: connection_id.to_string(),
+            sql: sql.to_string(),
+            expires_at: now + CONFIRMATION_TTL,
+        });
+        token
+    }
+
+    /// Use up `token`, returning whether it was issued for this SQL and connection and is still valid.
+    pub fn redeem(&self, token: &str, connection_id: &str, sql: &str) -> bool {
+        self.pending.lock().unwrap().remove(token).is_some_and(|confirmation| {
+            confirmation.connection_id == connection_id
+                && confirmation.sql == sql
+                && confirmation.expires_at > Instant::now()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptions(sql: &str) -> Vec<String> {
+        destructive_statements(&script::split_statements(sql)).into_iter()
+            .map(|statement| statement.description)
+            .collect()
+    }
+
+    #[test]
+    fn reads_and_targeted_changes_run_without_asking() {
+        for sql in [
+            "SELECT * FROM t",
+            "WITH a AS (SELECT 1) SELECT * FROM a",
+            "SELECT * FROM t FOR UPDATE",
+            "UPDATE t SET x = 1 WHERE id = 2",
+            "DELETE FROM t WHERE id = 2",
+            "INSERT INTO t VALUES (1) ON CONFLICT (id) DO UPDATE SET x = 1",
+            "WITH u AS (UPDATE t SET x = 1 WHERE id = 2 RETURNING *) SELECT * FROM u",
+            "CREATE TABLE t (id int)",
+            "ALTER TABLE t ADD COLUMN x int",
+        ] {
+            assert_eq!(descriptions(sql), Vec::<String>::new(), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn whole_table_changes_and_drops_ask() {
+        assert_eq!(descriptions("DELETE FROM t"), ["Deletes every row in t"]);
+        assert_eq!(descriptions("UPDATE s.t AS x SET a = 1"), ["Updates every row in s.t"]);
+        assert_eq!(descriptions("TRUNCATE a, b"), ["Removes every row from a, b"]);
+        assert_eq!(descriptions("DROP TABLE t CASCADE"), ["Drops table t and everything that depends on it"]);
+        assert_eq!(descriptions("ALTER TABLE t DROP COLUMN x"), ["Drops column x from t"]);
+        assert_eq!(descriptions("SELECT 1; DROP VIEW v"), ["Drops view v"]);
+    }
+
+    #[test]
+    fn data_modifying_ctes_ask() {
+        assert_eq!(
+            descriptions("WITH u AS (UPDATE t SET x = 1 RETURNING *) SELECT * FROM u"),
+            ["Updates every row in t"]
+        );
+        assert_eq!(
+            descriptions("WITH a AS (SELECT 1) UPDATE t SET x = 1"),
+            ["Updates every row in t"]
+        );
+        assert_eq!(
+            descriptions("INSERT INTO log WITH u AS (UPDATE t SET x = 1 RETURNING id) SELECT id FROM u"),
+            ["Updates every row in t"]
+        );
+        assert_eq!(
+            descriptions("WITH d AS (DELETE FROM t RETURNING *) SELECT * FROM d"),
+            ["Runs a DELETE statement that could not be checked"]
+        );
+        assert_eq!(
+            descriptions("with a as (select 1) delete from t"),
+            ["Runs a DELETE statement that could not be checked"]
+        );
+    }
+
+    #[test]
+    fn do_blocks_and_merge_ask() {
+        assert_eq!(
+            descriptions("DO $$ BEGIN DELETE FROM t; END $$"),
+            ["Runs a DO statement that could not be checked"]
+        );
+        assert_eq!(
+            descriptions("MERGE INTO t USING s ON t.id = s.id WHEN MATCHED THEN DELETE"),
+            ["Merges into t, which can update or delete its rows"]
+        );
+    }
+}
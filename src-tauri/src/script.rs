@@ -0,0 +1,580 @@
+// Words between CREATE/DROP/ALTER and the kind of object that the command tag leaves out
+const OBJECT_MODIFIERS: &[&str] = &[
+    "OR", "REPLACE", "TEMP", "TEMPORARY", "UNLOGGED", "GLOBAL", "LOCAL", "UNIQUE",
+    "RECURSIVE", "TRUSTED", "PROCEDURAL", "CONSTRAINT",
+];
+
+/// Split a script into statements on top-level semicolons. Semicolons inside
+/// string literals, quoted identifiers, dollar-quoted bodies and comments are
+/// left alone, and statements that are only whitespace or comments are dropped.
+pub fn split_statements(sql: &str) -> Vec<&str> {
+    let bytes = sql.as_bytes();
+    let mut statements = Vec::new();
+    let mut start = 0;
+    let mut has_content = false;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        let next = bytes.get(i + 1).copied();
+
+        match c {
+            b'-' if next == Some(b'-') => {
+                i = find_from(bytes, i, b"\n").map(|end| end + 1).unwrap_or(bytes.len());
+                continue;
+            },
+            b'/' if next == Some(b'*') => {
+                i = skip_block_comment(bytes, i);
+                continue;
+            },
+            b'\'' => {
+                // E'...' strings allow backslash escapes
+                let escapes = i > 0
+                    && matches!(bytes[i - 1], b'E' | b'e')
+                    && (i < 2 || !is_identifier_byte(bytes[i - 2]));
+                i = skip_quoted(bytes, i, b'\'', escapes);
+            },
+            b'"' => i = skip_quoted(bytes, i, b'"', false),
+            b'$' if i == 0 || !is_identifier_byte(bytes[i - 1]) => {
+                match dollar_tag(bytes, i) {
+                    Some(tag) => {
+                        let body = i + tag.len();
+                        i = find_from(bytes, body, tag).map(|end| end + tag.len()).unwrap_or(bytes.len());
+                    },
+                    None => i += 1,
+                }
+            },
+            b';' => {
+                if has_content {
+                    statements.push(sql[start..i].trim());
+                }
+                start = i + 1;
+                has_content = false;
+                i += 1;
+                continue;
+            },
+            _ => i += 1,
+        }
+
+        if !c.is_ascii_whitespace() {
+            has_content = true;
+        }
+    }
+
+    if has_content {
+        statements.push(sql[start..].trim());
+    }
+
+    statements
+}
+
+/// The first `count` keywords of a statement, uppercased. Comments, literals,
+/// quoted identifiers and anything in parentheses are skipped, except for
+/// parentheses the statement starts with, as in `(SELECT 1) UNION ...`.
+pub fn leading_keywords(sql: &str, count: usize) -> Vec<String> {
+    scan_keywords(sql, count, false)
+}
+
+/// Every keyword of a statement, uppercased, including those inside parentheses.
+pub fn keywords(sql: &str) -> Vec<String> {
+    scan_keywords(sql, usize::MAX, true)
+}
+
+/// Whether a statement is a query that only reads: a SELECT, VALUES or TABLE
+/// without data-modifying common table expressions or row locks like `FOR UPDATE`.
+/// Only the statement's own keywords count, not names or subqueries in it.
+pub fn is_plain_query(sql: &str) -> bool {
+    let keywords = leading_keywords(sql, usize::MAX);
+    let reads = match keywords.first().map(String::as_str) {
+        Some("SELECT" | "VALUES" | "TABLE") => true,
+        Some("WITH") => {
+            let (bodies, statement) = common_table_expressions(sql);
+            bodies.into_iter().all(is_plain_query) && is_plain_query(statement)
+        },
+        _ => false,
+    };
+    // FOR UPDATE, FOR NO KEY UPDATE, FOR SHARE and FOR KEY SHARE
+    let locks = keywords.windows(2).any(|pair| {
+        pair[0] == "FOR" && matches!(pair[1].as_str(), "UPDATE" | "NO" | "SHARE" | "KEY")
+    });
+    reads && !locks
+}
+
+enum Token<'s> {
+    Word(&'s str, usize),
+    Parenthesized(&'s str),
+}
+
+// The bodies of a WITH statement's common table expressions, and the statement they precede
+fn common_table_expressions(sql: &str) -> (Vec<&str>, &str) {
+    let tokens = top_level_tokens(sql);
+    let mut bodies = Vec::new();
+
+    for (n, token) in tokens.iter().enumerate() {
+        match *token {
+            // `name AS (...)`, `name AS [NOT] MATERIALIZED (...)`
+            Token::Parenthesized(body) => {
+                let after_as = n > 0 && matches!(tokens[n - 1], Token::Word(word, _)
+                    if word.eq_ignore_ascii_case("AS") || word.eq_ignore_ascii_case("MATERIALIZED"));
+                if after_as {
+                    bodies.push(body);
+                }
+            },
+            Token::Word(word, start) if !bodies.is_empty() => {
+                // INSERT and the like are unreserved, so they can also name the next expression
+                let names_expression = matches!(tokens.get(n + 1), Some(Token::Parenthesized(_)))
+                    || matches!(tokens.get(n + 1), Some(Token::Word(next, _)) if next.eq_ignore_ascii_case("AS"));
+                let statement = match word.to_uppercase().as_str() {
+                    "SELECT" | "VALUES" | "TABLE" => true,
+                    "INSERT" | "UPDATE" | "DELETE" | "MERGE" => !names_expression,
+                    _ => false,
+                };
+                if statement {
+                    return (bodies, &sql[start..]);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    (bodies, "")
+}
+
+// Words and parenthesized groups outside parentheses, skipping comments and literals
+fn top_level_tokens(sql: &str) -> Vec<Token<'_>> {
+    let bytes = sql.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        let next = bytes.get(i + 1).copied();
+
+        match c {
+            b'-' if next == Some(b'-') => i = find_from(bytes, i, b"\n").unwrap_or(bytes.len()),
+            b'/' if next == Some(b'*') => i = skip_block_comment(bytes, i),
+            b'\'' => i = skip_quoted(bytes, i, b'\'', false),
+            b'"' => {
+                let start = i;
+                i = skip_quoted(bytes, i, b'"', false);
+                tokens.push(Token::Word(&sql[start..i], start));
+            },
+            b'$' => {
+                match dollar_tag(bytes, i) {
+                    Some(tag) => {
+                        let body = i + tag.len();
+                        i = find_from(bytes, body, tag).map(|end| end + tag.len()).unwrap_or(bytes.len());
+                    },
+                    None => i += 1,
+                }
+            },
+            b'(' => {
+                let end = closing_parenthesis(bytes, i);
+                tokens.push(Token::Parenthesized(&sql[i + 1..end]));
+                i = (end + 1).min(bytes.len());
+            },
+            _ if is_identifier_byte(c) => {
+                let start = i;
+                while i < bytes.len() && is_identifier_byte(bytes[i]) {
+                    i += 1;
+                }
+                let word = &sql[start..i];
+                if bytes.get(i) == Some(&b'\'') {
+                    i = skip_quoted(bytes, i, b'\'', word.eq_ignore_ascii_case("E"));
+                } else if !c.is_ascii_digit() {
+                    tokens.push(Token::Word(word, start));
+                }
+            },
+            _ => i += 1,
+        }
+    }
+
+    tokens
+}
+
+// Where the parenthesis at `start` is closed, or the end of the input if it isn't
+fn closing_parenthesis(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        let next = bytes.get(i + 1).copied();
+
+        match c {
+            b'-' if next == Some(b'-') => i = find_from(bytes, i, b"\n").unwrap_or(bytes.len()),
+            b'/' if next == Some(b'*') => i = skip_block_comment(bytes, i),
+            b'\'' => {
+                let escapes = i > 0
+                    && matches!(bytes[i - 1], b'E' | b'e')
+                    && (i < 2 || !is_identifier_byte(bytes[i - 2]));
+                i = skip_quoted(bytes, i, b'\'', escapes);
+            },
+            b'"' => i = skip_quoted(bytes, i, b'"', false),
+            b'$' if i == 0 || !is_identifier_byte(bytes[i - 1]) => {
+                match dollar_tag(bytes, i) {
+                    Some(tag) => {
+                        let body = i + tag.len();
+                        i = find_from(bytes, body, tag).map(|end| end + tag.len()).unwrap_or(bytes.len());
+                    },
+                    None => i += 1,
+                }
+            },
+            b'(' => {
+                depth += 1;
+                i += 1;
+            },
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+                i += 1;
+            },
+            _ => i += 1,
+        }
+    }
+
+    bytes.len()
+}
+
+// Keywords outside literals and comments; with `nested`, inside parentheses too
+fn scan_keywords(sql: &str, count: usize, nested: bool) -> Vec<String> {
+    let bytes = sql.as_bytes();
+    let mut keywords = Vec::new();
+    let mut depth = 0usize;
+    let mut started = false;
+    let mut i = 0;
+
+    while i < bytes.len() && keywords.len() < count {
+        let c = bytes[i];
+        let next = bytes.get(i + 1).copied();
+
+        match c {
+            b'-' if next == Some(b'-') => i = find_from(bytes, i, b"\n").unwrap_or(bytes.len()),
+            b'/' if next == Some(b'*') => i = skip_block_comment(bytes, i),
+            b'\'' => i = skip_quoted(bytes, i, b'\'', false),
+            b'"' => i = skip_quoted(bytes, i, b'"', false),
+            b'$' => {
+                match dollar_tag(bytes, i) {
+                    Some(tag) => {
+                        let body = i + tag.len();
+                        i = find_from(bytes, body, tag).map(|end| end + tag.len()).unwrap_or(bytes.len());
+                    },
+                    None => i += 1,
+                }
+            },
+            b'(' => {
+                if started {
+                    depth += 1;
+                }
+                i += 1;
+            },
+            b')' => {
+                depth = depth.saturating_sub(1);
+                i += 1;
+            },
+            _ if is_identifier_byte(c) => {
+                let start = i;
+                while i < bytes.len() && is_identifier_byte(bytes[i]) {
+                    i += 1;
+                }
+                let word = &sql[start..i];
+                if bytes.get(i) == Some(&b'\'') {
+                    // A prefixed literal like E'...' or B'...'; only E'...' allows backslash escapes
+                    i = skip_quoted(bytes, i, b'\'', word.eq_ignore_ascii_case("E"));
+                } else if (depth == 0 || nested) && !c.is_ascii_digit() {
+                    keywords.push(word.to_uppercase());
+                }
+                started = true;
+            },
+            _ => i += 1,
+        }
+    }
+
+    keywords
+}
+
+/// The command a statement runs, named the way the server names it in the
+/// command tag: `SELECT`, `INSERT`, `CREATE TABLE`, ...
+pub fn command_name(sql: &str) -> String {
+    let keywords = leading_keywords(sql, usize::MAX);
+    let first = keywords.first().map(String::as_str).unwrap_or("");
+
+    match first {
+        // The statement after the common table expressions decides
+        "WITH" => keywords.iter()
+            .find(|keyword| matches!(keyword.as_str(), "SELECT" | "VALUES" | "TABLE" | "INSERT" | "UPDATE" | "DELETE" | "MERGE"))
+            .filter(|keyword| !matches!(keyword.as_str(), "VALUES" | "TABLE"))
+            .cloned()
+            .unwrap_or_else(|| "SELECT".to_string()),
+        "VALUES" | "TABLE" => "SELECT".to_string(),
+        "START" => "START TRANSACTION".to_string(),
+        "CREATE" | "DROP" | "ALTER" => {
+            let mut object = keywords[1..].iter()
+                .map(String::as_str)
+                .skip_while(|keyword| OBJECT_MODIFIERS.contains(keyword));
+            let kind = match object.next() {
+                Some(kind @ ("MATERIALIZED" | "FOREIGN" | "EVENT")) => {
+                    object.next().map_or(kind.to_string(), |second| format!("{} {}", kind, second))
+                },
+                Some(kind) => kind.to_string(),
+                None => return first.to_string(),
+            };
+
+            // CREATE TABLE ... AS and materialized views report the rows they were filled with
+            let filled = first == "CREATE"
+                && matches!(kind.as_str(), "TABLE" | "MATERIALIZED VIEW")
+                && keywords.iter().any(|keyword| keyword == "AS");
+            if filled {
+                "SELECT".to_string()
+            } else {
+                format!("{} {}", first, kind)
+            }
+        },
+        _ => first.to_string(),
+    }
+}
+
+/// The command tag for `command` having returned or changed `rows` rows, e.g.
+/// `INSERT 0 3` or `UPDATE 1`. Commands without a row count are tagged by name alone.
+pub fn command_tag(command: &str, rows: u64) -> String {
+    match command {
+        "INSERT" => format!("INSERT 0 {}", rows),
+        "SELECT" | "UPDATE" | "DELETE" | "MERGE" | "COPY" | "FETCH" | "MOVE" => format!("{} {}", command, rows),
+        _ => command.to_string(),
+    }
+}
+
+/// The statement with string literals, dollar-quoted bodies and numbers
+/// replaced by `?`, so it can be logged without the values in it.
+pub fn redact_literals(sql: &str) -> String {
+    let bytes = sql.as_bytes();
+    let mut redacted = String::with_capacity(sql.len());
+    let mut copied = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        let next = bytes.get(i + 1).copied();
+
+        let literal_end = match c {
+            b'-' if next == Some(b'-') => {
+                i = find_from(bytes, i, b"\n").map(|end| end + 1).unwrap_or(bytes.len());
+                continue;
+            },
+            b'/' if next == Some(b'*') => {
+                i = skip_block_comment(bytes, i);
+                continue;
+            },
+            b'\'' => {
+                let escapes = i > 0
+                    && matches!(bytes[i - 1], b'E' | b'e')
+                    && (i < 2 || !is_identifier_byte(bytes[i - 2]));
+                skip_quoted(bytes, i, b'\'', escapes)
+            },
+            b'"' => {
+                i = skip_quoted(bytes, i, b'"', false);
+                continue;
+            },
+            b'$' if i == 0 || !is_identifier_byte(bytes[i - 1]) => {
+                match dollar_tag(bytes, i) {
+                    Some(tag) => {
+                        let body = i + tag.len();
+                        find_from(bytes, body, tag).map(|end| end + tag.len()).unwrap_or(bytes.len())
+                    },
+                    None => {
+                        i += 1;
+                        continue;
+                    },
+                }
+            },
+            // Digits that start a token; `$1` and `t1` are left alone
+            b'0'..=b'9' if i == 0 || !is_identifier_byte(bytes[i - 1]) => {
+                let mut end = i;
+                while end < bytes.len() && (bytes[end].is_ascii_alphanumeric() || matches!(bytes[end], b'.' | b'_')) {
+                    end += 1;
+                }
+                end
+            },
+            _ => {
+                i += 1;
+                continue;
+            },
+        };
+
+        redacted.push_str(&sql[copied..i]);
+        redacted.push('?');
+        i = literal_end;
+        copied = i;
+    }
+
+    redacted.push_str(&sql[copied..]);
+    redacted
+}
+
+fn is_identifier_byte(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_' || c == b'$' || c >= 0x80
+}
+
+fn find_from(bytes: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    bytes.get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|pos| from + pos)
+}
+
+// Block comments nest in Postgres
+fn skip_block_comment(bytes: &[u8], start: usize) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        if bytes[i] == b'/' && bytes.get(i + 1) == Some(&b'*') {
+            depth += 1;
+            i += 2;
+        } else if bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/') {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+// A doubled quote is an escaped quote; with `escapes`, so is a backslashed one
+fn skip_quoted(bytes: &[u8], start: usize, quote: u8, escapes: bool) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        if escapes && bytes[i] == b'\\' {
+            i += 2;
+        } else if bytes[i] == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+            } else {
+                return i + 1;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+// `$$` or `$tag$`, where the tag is an identifier that doesn't start with a digit
+fn dollar_tag(bytes: &[u8], start: usize) -> Option<&[u8]> {
+    let mut i = start + 1;
+    if bytes.get(i).is_some_and(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    while let Some(&c) = bytes.get(i) {
+        if c == b'$' {
+            return Some(&bytes[start..=i]);
+        }
+        if !(c.is_ascii_alphanumeric() || c == b'_' || c >= 0x80) {
+            return None;
+        }
+        i += 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_string_literals() {
+        assert_eq!(redact_literals("SELECT * FROM t WHERE name = 'it''s' AND x = 'y'"), "SELECT * FROM t WHERE name = ? AND x = ?");
+        assert_eq!(redact_literals(r"SELECT E'a\'b; c', 'd'"), "SELECT E?, ?");
+        assert_eq!(redact_literals("SELECT \"Quoted 'Col'\" FROM t"), "SELECT \"Quoted 'Col'\" FROM t");
+    }
+
+    #[test]
+    fn redacts_dollar_quoted_bodies() {
+        assert_eq!(redact_literals("SELECT $$it's $1$$, $tag$ a $$ b $tag$"), "SELECT ?, ?");
+        assert_eq!(redact_literals("DO $body$ BEGIN RAISE NOTICE 'x'; END $body$"), "DO ?");
+    }
+
+    #[test]
+    fn redacts_numbers_but_not_parameters_or_names() {
+        assert_eq!(
+            redact_literals("SELECT t1.a, 42, 1.5e3 FROM t1 WHERE id = $1 AND n > 0x1F"),
+            "SELECT t1.a, ?, ? FROM t1 WHERE id = $1 AND n > ?"
+        );
+    }
+
+    #[test]
+    fn keeps_comments() {
+        assert_eq!(redact_literals("SELECT 1 -- it's 2\n/* 'x' */ , 3"), "SELECT ? -- it's 2\n/* 'x' */ , ?");
+    }
+
+    #[test]
+    fn plain_queries() {
+        assert!(is_plain_query("SELECT * FROM t"));
+        assert!(is_plain_query("VALUES (1), (2)"));
+        assert!(is_plain_query("(SELECT 1) UNION (SELECT 2)"));
+        assert!(is_plain_query("WITH a AS (SELECT 1), b AS MATERIALIZED (SELECT * FROM a) SELECT * FROM b"));
+        assert!(!is_plain_query("INSERT INTO t VALUES (1) RETURNING *"));
+        assert!(!is_plain_query("SHOW search_path"));
+    }
+
+    #[test]
+    fn data_modifying_ctes_and_row_locks_are_not_plain() {
+        assert!(!is_plain_query("WITH d AS (DELETE FROM t RETURNING *) SELECT * FROM d"));
+        assert!(!is_plain_query("WITH a AS (SELECT 1) UPDATE t SET x = 1 RETURNING *"));
+        assert!(!is_plain_query("WITH a AS (SELECT * FROM t FOR UPDATE) SELECT * FROM a"));
+        assert!(!is_plain_query("SELECT * FROM t FOR UPDATE"));
+        assert!(!is_plain_query("SELECT * FROM t FOR NO KEY UPDATE SKIP LOCKED"));
+        assert!(!is_plain_query("SELECT * FROM t FOR KEY SHARE"));
+        assert!(!is_plain_query("(SELECT * FROM t FOR SHARE) UNION ALL SELECT * FROM u"));
+    }
+
+    #[test]
+    fn names_that_are_keywords_are_plain() {
+        assert!(is_plain_query("SELECT update, share FROM t"));
+        assert!(is_plain_query("SELECT * FROM update WHERE delete > 0"));
+        assert!(is_plain_query("SELECT * FROM t WHERE x IN (SELECT merge FROM insert)"));
+        assert!(is_plain_query("WITH update AS (SELECT share FROM t) SELECT * FROM update"));
+        assert!(is_plain_query("WITH a AS (SELECT 'DELETE' AS update) SELECT * FROM a"));
+        assert!(is_plain_query("SELECT 'FOR UPDATE' -- FOR SHARE\nFROM t"));
+    }
+
+    #[test]
+    fn splits_on_top_level_semicolons() {
+        assert_eq!(split_statements("SELECT 1; SELECT 2;"), vec!["SELECT 1", "SELECT 2"]);
+        assert_eq!(split_statements("SELECT 1;\nSELECT 2"), vec!["SELECT 1", "SELECT 2"]);
+    }
+
+    #[test]
+    fn keeps_semicolons_in_dollar_quoted_bodies() {
+        let function = "CREATE FUNCTION f() RETURNS int AS $$ BEGIN RETURN 1; END; $$ LANGUAGE plpgsql";
+        assert_eq!(split_statements(&format!("{}; SELECT f()", function)), vec![function, "SELECT f()"]);
+        let tagged = "DO $body$ BEGIN RAISE NOTICE '$$;'; END $body$";
+        assert_eq!(split_statements(&format!("{};SELECT 2", tagged)), vec![tagged, "SELECT 2"]);
+    }
+
+    #[test]
+    fn keeps_semicolons_in_literals_and_quoted_identifiers() {
+        assert_eq!(split_statements(r"SELECT E'a\';b'; SELECT 2"), vec![r"SELECT E'a\';b'", "SELECT 2"]);
+        assert_eq!(split_statements("SELECT 'it''s;'; SELECT 2"), vec!["SELECT 'it''s;'", "SELECT 2"]);
+        assert_eq!(split_statements(r#"SELECT 1 AS "a;b"; SELECT 2"#), vec![r#"SELECT 1 AS "a;b""#, "SELECT 2"]);
+    }
+
+    #[test]
+    fn keeps_semicolons_in_comments() {
+        assert_eq!(split_statements("SELECT 1 /* a /* ; */ ; */; SELECT 2"), vec!["SELECT 1 /* a /* ; */ ; */", "SELECT 2"]);
+        assert_eq!(split_statements("SELECT 1 -- a; b\n; SELECT 2"), vec!["SELECT 1 -- a; b", "SELECT 2"]);
+    }
+
+    #[test]
+    fn drops_statements_without_content() {
+        assert!(split_statements("").is_empty());
+        assert!(split_statements("  \n\t ").is_empty());
+        assert!(split_statements("-- just a comment\n/* and ; another */").is_empty());
+        assert_eq!(split_statements(";; SELECT 1 ;  ; -- done"), vec!["SELECT 1"]);
+    }
+}
@@ -0,0 +1,381 @@
+use serde::{Deserialize, Serialize};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use tauri::{Emitter, Manager};
+use tokio::task::JoinHandle;
+use tokio_postgres::Client;
+use uuid::Uuid;
+
+use crate::connection;
+use crate::error::AppError;
+use crate::notices::Notices;
+use crate::pool::PooledClient;
+use crate::script;
+use crate::DatabaseConnection;
+
+/// A client opened by `connect_to_database` and kept alive until disconnect, so
+/// `SET`, temp tables and prepared statements persist between commands.
+pub struct Session {
+    id: String,
+    pub connection_id: String,
+    pub client: Client,
+    notices: Notices,
+    app: tauri::AppHandle,
+    transaction_status: Mutex<TransactionStatus>,
+    /// The cursor of a paged result the app opened a transaction for, see `paging::open_cursor`
+    result_transaction: Mutex<Option<String>>,
+    watcher: Mutex<Option<JoinHandle<()>>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionStatus {
+    Idle,
+    InTransaction,
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IsolationLevel {
+    ReadUncommitted,
+    ReadCommitted,
+    RepeatableRead,
+    Serializable,
+}
+
+impl IsolationLevel {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            IsolationLevel::ReadUncommitted => "READ UNCOMMITTED",
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct TransactionStatusPayload {
+    connection_id: String,
+    status: TransactionStatus,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ConnectionLostPayload {
+    connection_id: String,
+    error: Option<String>,
+}
+
+pub struct SessionManager {
+    active: Mutex<Option<Arc<Session>>>,
+}
+
+impl SessionManager {
+    pub fn new() -> Self {
+        SessionManager {
+            active: Mutex::new(None),
+        }
+    }
+
+    /// Open a session for the connection, replacing the current one.
+    pub async fn open(&self, app: &tauri::AppHandle, connection: &DatabaseConnection) -> Result<(), AppError> {
+        let (client, notices, driver) = connection::connect(app, connection).await?;
+
+        let session = Arc::new(Session {
+            id: Uuid::new_v4().to_string(),
+            connection_id: connection.id.clone(),
+            client,
+            notices,
+            app: app.clone(),
+            transaction_status: Mutex::new(TransactionStatus::Idle),
+            result_transaction: Mutex::new(None),
+            watcher: Mutex::new(None),
+        });
+
+        // Tell the frontend when the server drops the socket under us
+        let app_handle = app.clone();
+        let session_id = session.id.clone();
+        let connection_id = session.connection_id.clone();
+        let watcher = tokio::spawn(async move {
+            let error = match driver.await {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => Some(e),
+                Err(e) => Some(e.to_string()),
+            };
+
+            let sessions = app_handle.state::<SessionManager>();
+            if sessions.take_if_current(&session_id).is_some() {
+                let payload = ConnectionLostPayload { connection_id, error };
+                if let Err(e) = app_handle.emit("connection-lost", payload) {
+                    log::error!("Failed to emit connection-lost: {}", e);
+                }
+            }
+        });
+        *session.watcher.lock().unwrap() = Some(watcher);
+
+        if let Some(previous) = self.active.lock().unwrap().replace(session) {
+            previous.stop_watching();
+        }
+
+        Ok(())
+    }
+
+    /// Close the active session, if any.
+    pub fn close(&self) {
+        if let Some(session) = self.active.lock().unwrap().take() {
+            session.stop_watching();
+        }
+    }
+
+    pub fn active(&self) -> Option<Arc<Session>> {
+        self.active.lock().unwrap().clone()
+    }
+
+    /// The active session, if it belongs to the given connection.
+    pub fn get(&self, connection_id: &str) -> Option<Arc<Session>> {
+        self.active.lock().unwrap()
+            .as_ref()
+            .filter(|session| session.connection_id == connection_id)
+            .cloned()
+    }
+
+    pub fn active_connection_id(&self) -> Option<String> {
+        self.active.lock().unwrap()
+            .as_ref()
+            .map(|session| session.connection_id.clone())
+    }
+
+    fn take_if_current(&self, session_id: &str) -> Option<Arc<Session>> {
+        let mut active = self.active.lock().unwrap();
+        if active.as_ref().is_some_and(|session| session.id == session_id) {
+            active.take()
+        } else {
+            None
+        }
+    }
+}
+
+impl Session {
+    // Closing on purpose must not be reported as a lost connection
+    fn stop_watching(&self) {
+        if let Some(watcher) = self.watcher.lock().unwrap().take() {
+            watcher.abort();
+        }
+    }
+
+    pub fn transaction_status(&self) -> TransactionStatus {
+        *self.transaction_status.lock().unwrap()
+    }
+
+    fn set_transaction_status(&self, status: TransactionStatus) {
+        let changed = {
+            let mut current = self.transaction_status.lock().unwrap();
+            let changed = *current != status;
+            *current = status;
+            changed
+        };
+
+        if changed {
+            let payload = TransactionStatusPayload {
+                connection_id: self.connection_id.clone(),
+                status,
+            };
+            if let Err(e) = self.app.emit("transaction-status", payload) {
+                log::error!("Failed to emit transaction-status: {}", e);
+            }
+        }
+    }
+
+    /// End the transaction a paged result was left open in, closing the result,
+    /// so the next command doesn't run inside it.
+    pub async fn end_result_transaction(&self) {
+        if self.take_result_transaction(None) {
+            if let Err(e) = self.client.batch_execute("COMMIT").await {
+                log::warn!("Failed to close paged result: {}", e);
+            }
+        }
+    }
+
+    // Forget the paged result's transaction if it is still open and, given a cursor, belongs to it
+    fn take_result_transaction(&self, cursor: Option<&str>) -> bool {
+        let mut held = self.result_transaction.lock().unwrap();
+        let matches = match (held.as_deref(), cursor) {
+            (Some(held), Some(cursor)) => held == cursor,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if matches {
+            *held = None;
+        }
+        matches
+    }
+
+    pub async fn begin(&self, isolation_level: Option<IsolationLevel>, read_only: bool) -> Result<(), AppError> {
+        if self.transaction_status() != TransactionStatus::Idle {
+            return Err(AppError::Transaction("A transaction is already open".to_string()));
+        }
+        self.end_result_transaction().await;
+
+        let mut sql = "BEGIN".to_string();
+        if let Some(level) = isolation_level {
+            sql.push_str(&format!(" ISOLATION LEVEL {}", level.as_sql()));
+        }
+        if read_only {
+            sql.push_str(" READ ONLY");
+        }
+
+        self.client.batch_execute(&sql).await
+            .map_err(|e| AppError::Transaction(format!("Failed to begin transaction: {}", e)))?;
+        self.set_transaction_status(TransactionStatus::InTransaction);
+        Ok(())
+    }
+
+    pub async fn commit(&self) -> Result<(), AppError> {
+        // COMMIT ends the transaction whether or not it succeeds; in a failed
+        // transaction the server turns it into a ROLLBACK.
+        let was_failed = self.transaction_status() == TransactionStatus::Failed;
+        let result = self.client.batch_execute("COMMIT").await;
+        self.set_transaction_status(TransactionStatus::Idle);
+        result.map_err(|e| AppError::Transaction(format!("Failed to commit transaction: {}", e)))?;
+
+        if was_failed {
+            return Err(AppError::Transaction("Transaction was aborted by an earlier error and has been rolled back".to_string()));
+        }
+        Ok(())
+    }
+
+    pub async fn rollback(&self) -> Result<(), AppError> {
+        let result = self.client.batch_execute("ROLLBACK").await;
+        self.set_transaction_status(TransactionStatus::Idle);
+        result.map_err(|e| AppError::Transaction(format!("Failed to roll back transaction: {}", e)))
+    }
+
+    pub async fn savepoint(&self, name: &str) -> Result<(), AppError> {
+        self.require_transaction()?;
+        let result = self.client.batch_execute(&format!("SAVEPOINT {}", quote_identifier(name))).await;
+        self.track_result(result.is_ok());
+        result.map_err(|e| AppError::Transaction(format!("Failed to create savepoint: {}", e)))
+    }
+
+    pub async fn rollback_to_savepoint(&self, name: &str) -> Result<(), AppError> {
+        self.require_transaction()?;
+        self.client.batch_execute(&format!("ROLLBACK TO SAVEPOINT {}", quote_identifier(name))).await
+            .map_err(|e| AppError::Transaction(format!("Failed to roll back to savepoint: {}", e)))?;
+        // Rolling back to a savepoint recovers a failed transaction
+        self.set_transaction_status(TransactionStatus::InTransaction);
+        Ok(())
+    }
+
+    pub async fn release_savepoint(&self, name: &str) -> Result<(), AppError> {
+        self.require_transaction()?;
+        let result = self.client.batch_execute(&format!("RELEASE SAVEPOINT {}", quote_identifier(name))).await;
+        self.track_result(result.is_ok());
+        result.map_err(|e| AppError::Transaction(format!("Failed to release savepoint: {}", e)))
+    }
+
+    fn require_transaction(&self) -> Result<(), AppError> {
+        if self.transaction_status() == TransactionStatus::Idle {
+            return Err(AppError::Transaction("No transaction is open".to_string()));
+        }
+        Ok(())
+    }
+
+    fn track_result(&self, succeeded: bool) {
+        if !succeeded && self.transaction_status() == TransactionStatus::InTransaction {
+            self.set_transaction_status(TransactionStatus::Failed);
+        }
+    }
+
+    /// Update the tracked transaction status after running ad-hoc SQL on the
+    /// session, so `BEGIN`/`COMMIT` typed into the editor are picked up too.
+    pub fn track_statement(&self, sql: &str, succeeded: bool) {
+        let keywords = script::leading_keywords(sql, 2);
+        let first = keywords.first().map(String::as_str).unwrap_or("");
+        let second = keywords.get(1).map(String::as_str).unwrap_or("");
+
+        match (first, second) {
+            ("ROLLBACK", "TO") if succeeded => self.set_transaction_status(TransactionStatus::InTransaction),
+            ("ROLLBACK", "TO") => self.track_result(false),
+            ("BEGIN", _) | ("START", _) => {
+                if succeeded {
+                    self.set_transaction_status(TransactionStatus::InTransaction);
+                }
+            },
+            // These end the transaction even when they fail
+            ("COMMIT", _) | ("END", _) | ("ROLLBACK", _) | ("ABORT", _) | ("PREPARE", "TRANSACTION") => {
+                self.set_transaction_status(TransactionStatus::Idle)
+            },
+            _ => self.track_result(succeeded),
+        }
+    }
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// A client to run a command on: the live session when it belongs to the
+/// connection, otherwise one checked out of the pool.
+pub enum DbClient {
+    Session(Arc<Session>),
+    Pooled(PooledClient),
+}
+
+impl DbClient {
+    pub fn notices(&self) -> &Notices {
+        match self {
+            DbClient::Session(session) => &session.notices,
+            DbClient::Pooled(client) => client.notices(),
+        }
+    }
+
+    /// Whether the session is inside a transaction block; pooled clients start outside one.
+    pub fn in_transaction(&self) -> bool {
+        match self {
+            DbClient::Session(session) => session.transaction_status() != TransactionStatus::Idle,
+            DbClient::Pooled(_) => false,
+        }
+    }
+
+    /// Let a pooled client's slot go while it is held for a long time.
+    pub fn detach(&mut self) {
+        if let DbClient::Pooled(client) = self {
+            client.detach();
+        }
+    }
+
+    /// Note that `cursor` was opened in a transaction of its own. On the session,
+    /// that transaction is ended before the next command runs.
+    pub fn hold_result_transaction(&self, cursor: &str) {
+        if let DbClient::Session(session) = self {
+            *session.result_transaction.lock().unwrap() = Some(cursor.to_string());
+        }
+    }
+
+    /// End the transaction opened for `cursor`, unless another command already did.
+    pub async fn end_result_transaction(&self, cursor: &str) -> Result<(), tokio_postgres::Error> {
+        match self {
+            DbClient::Session(session) if !session.take_result_transaction(Some(cursor)) => Ok(()),
+            _ => self.batch_execute("COMMIT").await,
+        }
+    }
+
+    /// Keep the session's transaction status in sync; pooled clients are reset after use.
+    pub fn track_statement(&self, sql: &str, succeeded: bool) {
+        if let DbClient::Session(session) = self {
+            session.track_statement(sql, succeeded);
+        }
+    }
+}
+
+impl Deref for DbClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        match self {
+            DbClient::Session(session) => &session.client,
+            DbClient::Pooled(client) => client,
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use native_tls::{Certificate, Identity, TlsConnector};
+use postgres_native_tls::MakeTlsConnector;
+use serde::{Deserialize, Serialize};
+
+/// libpq-compatible SSL modes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SslMode {
+    Disable,
+    Prefer,
+    Require,
+    VerifyCa,
+    VerifyFull,
+}
+
+impl SslMode {
+    /// Mode for the connection config. tokio-postgres only knows
+    /// disable/prefer/require; certificate verification is done by the connector.
+    pub fn config_mode(&self) -> tokio_postgres::config::SslMode {
+        match self {
+            SslMode::Disable => tokio_postgres::config::SslMode::Disable,
+            SslMode::Prefer => tokio_postgres::config::SslMode::Prefer,
+            SslMode::Require | SslMode::VerifyCa | SslMode::VerifyFull => tokio_postgres::config::SslMode::Require,
+        }
+    }
+}
+
+/// TLS options of a connection, borrowed from whichever request or stored connection carries them.
+pub struct TlsSettings<'a> {
+    pub ssl: Option<bool>,
+    pub ssl_mode: Option<SslMode>,
+    pub root_cert: Option<&'a str>,
+    pub client_cert: Option<&'a str>,
+    pub client_key: Option<&'a str>,
+}
+
+impl TlsSettings<'_> {
+    /// The explicit `ssl_mode` wins; older connections only have the `ssl` flag.
+    pub fn mode(&self) -> SslMode {
+        match self.ssl_mode {
+            Some(mode) => mode,
+            None if self.ssl.unwrap_or(false) => SslMode::Require,
+            None => SslMode::Disable,
+        }
+    }
+
+    pub fn connector(&self) -> Result<MakeTlsConnector, String> {
+        let mode = self.mode();
+        let mut builder = TlsConnector::builder();
+
+        // The connector is never used when TLS is off, so don't fail on bad cert paths
+        if mode == SslMode::Disable {
+            let connector = builder.build()
+                .map_err(|e| format!("Failed to create TLS connector: {}", e))?;
+            return Ok(MakeTlsConnector::new(connector));
+        }
+
+        if let Some(path) = non_empty(self.root_cert) {
+            for cert in read_root_certs(path)? {
+                builder.add_root_certificate(cert);
+            }
+        }
+
+        match (non_empty(self.client_cert), non_empty(self.client_key)) {
+            (Some(cert_path), Some(key_path)) => {
+                let cert = std::fs::read(cert_path)
+                    .map_err(|e| format!("Failed to read client certificate {}: {}", cert_path, e))?;
+                let key = std::fs::read(key_path)
+                    .map_err(|e| format!("Failed to read client key {}: {}", key_path, e))?;
+                let identity = Identity::from_pkcs8(&cert, &key)
+                    .map_err(|e| format!("Invalid client certificate or key (expected PEM, PKCS#8 key): {}", e))?;
+                builder.identity(identity);
+            },
+            (None, None) => {},
+            _ => return Err("Client certificate and client key must be provided together".to_string()),
+        }
+
+        // Same semantics as libpq: prefer/require only encrypt, unless a root CA was
+        // given to require, in which case it behaves like verify-ca.
+        match mode {
+            SslMode::Disable | SslMode::Prefer => {
+                builder.danger_accept_invalid_certs(true);
+            },
+            SslMode::Require if non_empty(self.root_cert).is_none() => {
+                builder.danger_accept_invalid_certs(true);
+            },
+            SslMode::Require | SslMode::VerifyCa => {
+                builder.danger_accept_invalid_hostnames(true);
+            },
+            SslMode::VerifyFull => {},
+        }
+
+        let connector = builder.build()
+            .map_err(|e| format!("Failed to create TLS connector: {}", e))?;
+
+        Ok(MakeTlsConnector::new(connector))
+    }
+}
+
+fn non_empty(value: Option<&str>) -> Option<&str> {
+    value.map(str::trim).filter(|v| !v.is_empty())
+}
+
+/// Load every certificate from a PEM bundle (or a single DER certificate).
+fn read_root_certs(path: &str) -> Result<Vec<Certificate>, String> {
+    let bytes = std::fs::read(path)
+        .map_err(|e| format!("Failed to read root certificate {}: {}", path, e))?;
+
+    let text = match std::str::from_utf8(&bytes) {
+        Ok(text) if text.contains("-----BEGIN CERTIFICATE-----") => text,
+        _ => {
+            let cert = Certificate::from_der(&bytes)
+                .map_err(|e| format!("Invalid root certificate {}: {}", path, e))?;
+            return Ok(vec![cert]);
+        }
+    };
+
+    const END: &str = "-----END CERTIFICATE-----";
+    let mut certs = Vec::new();
+    for block in text.split_inclusive(END).filter(|b| b.contains(END)) {
+        let cert = Certificate::from_pem(block.trim().as_bytes())
+            .map_err(|e| format!("Invalid root certificate {}: {}", path, e))?;
+        certs.push(cert);
+    }
+
+    Ok(certs)
+}
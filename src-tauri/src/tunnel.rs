@@ -0,0 +1,522 @@
+use serde::{Deserialize, Serialize};
+use ssh2::{CheckResult, Channel, HashType, KnownHostFileKind, KnownHosts, Session};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine};
+
+use crate::encryption;
+use crate::error::AppError;
+
+const DEFAULT_SSH_PORT: u16 = 22;
+const SSH_TIMEOUT: Duration = Duration::from_secs(10);
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+// How long the forwarding thread sleeps when no socket had anything to do. It
+// sleeps longer the longer the tunnel stays idle, up to the maximum
+const IDLE_POLL_MIN: Duration = Duration::from_millis(1);
+const IDLE_POLL_MAX: Duration = Duration::from_millis(25);
+
+/// SSH bastion a connection is reached through. `password` and `passphrase`
+/// are stored encrypted like the database password.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SshTunnel {
+    pub host: String,
+    pub port: Option<u16>,
+    pub username: String,
+    pub password: Option<String>,
+    /// Private key file; takes precedence over `password`. With neither, the SSH agent is used.
+    pub private_key_path: Option<String>,
+    pub passphrase: Option<String>,
+    /// Defaults to `~/.ssh/known_hosts`
+    pub known_hosts_path: Option<String>,
+    /// Add hosts missing from known_hosts instead of refusing them, like
+    /// `StrictHostKeyChecking=accept-new`. A changed key is always refused.
+    pub trust_new_host: Option<bool>,
+}
+
+impl SshTunnel {
+    /// A copy with its secrets encrypted, for storing with the connection `connection_id`.
+    pub fn encrypted(mut self, connection_id: &str) -> Result<Self, AppError> {
+        self.password = encrypt_secret(self.password, connection_id, encryption::SSH_PASSWORD_FIELD)?;
+        self.passphrase = encrypt_secret(self.passphrase, connection_id, encryption::SSH_PASSPHRASE_FIELD)?;
+        Ok(self)
+    }
+
+    /// A copy with the secrets of a stored connection decrypted, for opening the tunnel.
+    pub fn decrypted(&self, connection_id: &str) -> Result<Self, AppError> {
+        Ok(SshTunnel {
+            password: decrypt_secret(&self.password, connection_id, encryption::SSH_PASSWORD_FIELD)?,
+            passphrase: decrypt_secret(&self.passphrase, connection_id, encryption::SSH_PASSPHRASE_FIELD)?,
+            ..self.clone()
+        })
+    }
+
+    fn port(&self) -> u16 {
+        self.port.unwrap_or(DEFAULT_SSH_PORT)
+    }
+
+    fn known_hosts_path(&self) -> Option<PathBuf> {
+        match non_empty(&self.known_hosts_path) {
+            Some(path) => Some(PathBuf::from(path)),
+            None => home_dir().map(|home| home.join(".ssh").join("known_hosts")),
+        }
+    }
+}
+
+fn encrypt_secret(secret: Option<String>, connection_id: &str, field: &str) -> Result<Option<String>, AppError> {
+    match secret {
+        Some(secret) if !secret.is_empty() && !encryption::is_encrypted(&secret) => {
+            encryption::encrypt_password(&secret, connection_id, field)
+                .map(Some)
+                .map_err(|e| AppError::encryption("Failed to encrypt SSH secret", e))
+        },
+        secret => Ok(secret),
+    }
+}
+
+fn decrypt_secret(secret: &Option<String>, connection_id: &str, field: &str) -> Result<Option<String>, AppError> {
+    match secret.as_deref() {
+        Some("") | None => Ok(None),
+        Some(secret) => {
+            encryption::decrypt_password(secret, connection_id, field)
+                .map(Some)
+                .map_err(|e| AppError::encryption("Failed to decrypt SSH secret", e))
+        },
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|value| !value.is_empty())
+}
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+fn tunnel_error(context: &str, e: impl std::fmt::Display) -> AppError {
+    AppError::Tunnel(format!("{}: {}", context, e))
+}
+
+/// A local port forwarded to the database through the bastion. The forward
+/// stops when the last reference is dropped or the SSH session dies.
+pub struct Tunnel {
+    local_port: u16,
+    closed: Arc<AtomicBool>,
+}
+
+impl Tunnel {
+    /// Connect to the bastion and start forwarding a local port to `remote_host:remote_port`.
+    pub async fn open(config: &SshTunnel, remote_host: &str, remote_port: u16) -> Result<Self, AppError> {
+        let config = config.clone();
+        let remote_host = remote_host.to_string();
+        tokio::task::spawn_blocking(move || Tunnel::open_blocking(&config, remote_host, remote_port))
+            .await
+            .map_err(|e| tunnel_error("SSH tunnel task failed", e))?
+    }
+
+    fn open_blocking(config: &SshTunnel, remote_host: String, remote_port: u16) -> Result<Self, AppError> {
+        let session = connect_session(config)?;
+
+        let listener = TcpListener::bind(("127.0.0.1", 0))
+            .map_err(|e| tunnel_error("Failed to open local port for SSH tunnel", e))?;
+        let local_port = listener.local_addr()
+            .map_err(|e| tunnel_error("Failed to open local port for SSH tunnel", e))?
+            .port();
+        listener.set_nonblocking(true)
+            .map_err(|e| tunnel_error("Failed to open local port for SSH tunnel", e))?;
+
+        let closed = Arc::new(AtomicBool::new(false));
+        let forwarder = Forwarder {
+            session,
+            listener,
+            remote_host,
+            remote_port,
+            closed: closed.clone(),
+        };
+        std::thread::Builder::new()
+            .name(format!("ssh-tunnel-{}", local_port))
+            .spawn(move || forwarder.run())
+            .map_err(|e| tunnel_error("Failed to start SSH tunnel", e))?;
+
+        Ok(Tunnel { local_port, closed })
+    }
+
+    pub fn local_port(&self) -> u16 {
+        self.local_port
+    }
+
+    pub fn is_alive(&self) -> bool {
+        !self.closed.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Tunnel {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+fn connect_session(config: &SshTunnel) -> Result<Session, AppError> {
+    let address = (config.host.as_str(), config.port())
+        .to_socket_addrs()
+        .map_err(|e| tunnel_error(&format!("Failed to resolve SSH host {}", config.host), e))?
+        .next()
+        .ok_or_else(|| AppError::Tunnel(format!("Failed to resolve SSH host {}", config.host)))?;
+    let tcp = TcpStream::connect_timeout(&address, SSH_TIMEOUT)
+        .map_err(|e| tunnel_error(&format!("Failed to reach SSH host {}:{}", config.host, config.port()), e))?;
+
+    let mut session = Session::new().map_err(|e| tunnel_error("Failed to start SSH session", e))?;
+    session.set_tcp_stream(tcp);
+    session.set_timeout(SSH_TIMEOUT.as_millis() as u32);
+    session.handshake().map_err(|e| tunnel_error("SSH handshake failed", e))?;
+
+    verify_host_key(&session, config)?;
+    authenticate(&session, config)?;
+
+    session.set_keepalive(false, KEEPALIVE_INTERVAL.as_secs() as u32);
+    Ok(session)
+}
+
+/// Check the bastion's key against known_hosts, so credentials are never sent to an impostor.
+fn verify_host_key(session: &Session, config: &SshTunnel) -> Result<(), AppError> {
+    let (key, key_type) = session.host_key()
+        .ok_or_else(|| AppError::Tunnel("SSH host did not send a host key".to_string()))?;
+    let fingerprint = session.host_key_hash(HashType::Sha256)
+        .map(|hash| format!("SHA256:{}", STANDARD_NO_PAD.encode(hash)))
+        .unwrap_or_default();
+
+    let path = config.known_hosts_path()
+        .ok_or_else(|| AppError::Tunnel("Could not locate known_hosts; set a known_hosts path".to_string()))?;
+    let mut known_hosts = session.known_hosts()
+        .map_err(|e| tunnel_error("Failed to read known_hosts", e))?;
+    if path.exists() {
+        known_hosts.read_file(&path, KnownHostFileKind::OpenSSH)
+            .map_err(|e| tunnel_error(&format!("Failed to read {}", path.display()), e))?;
+    }
+
+    match known_hosts.check_port(&config.host, config.port(), key) {
+        CheckResult::Match => Ok(()),
+        CheckResult::Mismatch => Err(AppError::Tunnel(format!(
+            "Host key of {} does not match {} ({}). It may have been replaced, or someone may be intercepting the connection.",
+            config.host, path.display(), fingerprint
+        ))),
+        CheckResult::NotFound if config.trust_new_host.unwrap_or(false) => {
+            let host = match config.port() {
+                DEFAULT_SSH_PORT => config.host.clone(),
+                port => format!("[{}]:{}", config.host, port),
+            };
+            known_hosts.add(&host, key, "added by QueryOwl", key_type.into())
+                .map_err(|e| tunnel_error("Failed to add host key", e))?;
+            append_known_host(&known_hosts, &host, &path)?;
+            log::info!("Added SSH host key of {} ({}) to {}", config.host, fingerprint, path.display());
+            Ok(())
+        },
+        CheckResult::NotFound => Err(AppError::Tunnel(format!(
+            "SSH host {} is not in {} (key fingerprint {})",
+            config.host, path.display(), fingerprint
+        ))),
+        CheckResult::Failure => Err(AppError::Tunnel(format!("Failed to check the host key of {}", config.host))),
+    }
+}
+
+/// Append the entry just added for `host` to the known_hosts file. The rest of
+/// the file is left as it is, since writing it back out through libssh2 would
+/// drop comments and lines it doesn't understand.
+fn append_known_host(known_hosts: &KnownHosts, host: &str, path: &Path) -> Result<(), AppError> {
+    let entry = known_hosts.hosts()
+        .map_err(|e| tunnel_error("Failed to add host key", e))?
+        .into_iter()
+        .rev()
+        .find(|entry| entry.name() == Some(host))
+        .ok_or_else(|| AppError::Tunnel("Failed to add host key".to_string()))?;
+    let mut line = known_hosts.write_string(&entry, KnownHostFileKind::OpenSSH)
+        .map_err(|e| tunnel_error("Failed to add host key", e))?;
+    if !line.ends_with('\n') {
+        line.push('\n');
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| tunnel_error(&format!("Failed to create {}", dir.display()), e))?;
+    }
+    let write_error = |e: io::Error| tunnel_error(&format!("Failed to write {}", path.display()), e);
+    let mut file = OpenOptions::new().read(true).append(true).create(true).open(path)
+        .map_err(write_error)?;
+
+    // Start on a line of our own if the file doesn't end with a newline
+    if file.metadata().map_err(write_error)?.len() > 0 {
+        let mut last = [0u8; 1];
+        file.seek(SeekFrom::End(-1)).map_err(write_error)?;
+        file.read_exact(&mut last).map_err(write_error)?;
+        if last[0] != b'\n' {
+            line.insert(0, '\n');
+        }
+    }
+    file.write_all(line.as_bytes()).map_err(write_error)
+}
+
+fn authenticate(session: &Session, config: &SshTunnel) -> Result<(), AppError> {
+    // Secrets are in plain text here, see `SshTunnel::decrypted`
+    let password = config.password.as_deref().filter(|password| !password.is_empty());
+    let result = match (non_empty(&config.private_key_path), password) {
+        (Some(key_path), _) => {
+            let passphrase = config.passphrase.as_deref().filter(|passphrase| !passphrase.is_empty());
+            session.userauth_pubkey_file(&config.username, None, Path::new(key_path), passphrase)
+        },
+        (None, Some(password)) => session.userauth_password(&config.username, password),
+        (None, None) => session.userauth_agent(&config.username),
+    };
+
+    match result {
+        Ok(()) if session.authenticated() => Ok(()),
+        Ok(()) => Err(AppError::Tunnel(format!("SSH authentication failed for {}@{}", config.username, config.host))),
+        Err(e) => Err(tunnel_error(&format!("SSH authentication failed for {}@{}", config.username, config.host), e)),
+    }
+}
+
+/// Runs on its own thread: accepts local clients and shuttles bytes between
+/// each of them and an SSH channel to the database. libssh2 sessions can't be
+/// used from several threads at once, so every forward is pumped from this one
+/// thread with non-blocking sockets.
+struct Forwarder {
+    session: Session,
+    listener: TcpListener,
+    remote_host: String,
+    remote_port: u16,
+    closed: Arc<AtomicBool>,
+}
+
+impl Forwarder {
+    fn run(self) {
+        let mut forwards: Vec<Forward> = Vec::new();
+        let mut buffer = vec![0u8; 32 * 1024];
+        let mut last_keepalive = Instant::now();
+        let mut idle_poll = IDLE_POLL_MIN;
+        self.session.set_blocking(false);
+
+        while !self.closed.load(Ordering::Relaxed) {
+            let mut busy = false;
+
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    busy = true;
+                    match self.open_forward(stream) {
+                        Ok(forward) => forwards.push(forward),
+                        Err(e) => log::error!("Failed to open SSH tunnel channel: {}", e),
+                    }
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {},
+                Err(e) => log::error!("SSH tunnel failed to accept a connection: {}", e),
+            }
+
+            forwards.retain_mut(|forward| match forward.pump(&mut buffer) {
+                Ok(progress) => {
+                    busy |= progress;
+                    !forward.is_done()
+                },
+                Err(e) => {
+                    log::warn!("SSH tunnel connection closed: {}", e);
+                    false
+                },
+            });
+
+            if last_keepalive.elapsed() >= KEEPALIVE_INTERVAL {
+                last_keepalive = Instant::now();
+                if let Err(e) = self.session.keepalive_send() {
+                    let e = io::Error::from(e);
+                    if e.kind() != io::ErrorKind::WouldBlock {
+                        log::error!("SSH tunnel lost its session: {}", e);
+                        break;
+                    }
+                }
+            }
+
+            if busy {
+                idle_poll = IDLE_POLL_MIN;
+            } else {
+                std::thread::sleep(idle_poll);
+                idle_poll = (idle_poll * 2).min(IDLE_POLL_MAX);
+            }
+        }
+
+        self.closed.store(true, Ordering::Relaxed);
+    }
+
+    fn open_forward(&self, stream: TcpStream) -> io::Result<Forward> {
+        stream.set_nonblocking(true)?;
+        // Channel setup is a short exchange; do it blocking rather than retrying by hand
+        self.session.set_blocking(true);
+        let channel = self.session.channel_direct_tcpip(&self.remote_host, self.remote_port, None);
+        self.session.set_blocking(false);
+
+        Ok(Forward {
+            stream,
+            channel: channel?,
+            to_channel: Vec::new(),
+            to_stream: Vec::new(),
+            local_eof: false,
+            remote_eof: false,
+        })
+    }
+}
+
+/// One local client and its channel, with bytes read from one side that the other hasn't taken yet.
+struct Forward {
+    stream: TcpStream,
+    channel: Channel,
+    to_channel: Vec<u8>,
+    to_stream: Vec<u8>,
+    local_eof: bool,
+    remote_eof: bool,
+}
+
+impl Forward {
+    /// Move whatever can be moved without blocking; returns whether anything was.
+    fn pump(&mut self, buffer: &mut [u8]) -> io::Result<bool> {
+        let mut progress = false;
+
+        if self.to_channel.is_empty() && !self.local_eof {
+            match self.stream.read(buffer) {
+                Ok(0) => {
+                    self.local_eof = true;
+                    progress = true;
+                    let _ = self.channel.send_eof();
+                },
+                Ok(n) => {
+                    self.to_channel.extend_from_slice(&buffer[..n]);
+                    progress = true;
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {},
+                Err(e) => return Err(e),
+            }
+        }
+        if !self.to_channel.is_empty() {
+            match self.channel.write(&self.to_channel) {
+                Ok(n) => {
+                    self.to_channel.drain(..n);
+                    progress |= n > 0;
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {},
+                Err(e) => return Err(e),
+            }
+        }
+
+        if self.to_stream.is_empty() && !self.remote_eof {
+            match self.channel.read(buffer) {
+                Ok(0) => {
+                    if self.channel.eof() {
+                        self.remote_eof = true;
+                        progress = true;
+                    }
+                },
+                Ok(n) => {
+                    self.to_stream.extend_from_slice(&buffer[..n]);
+                    progress = true;
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {},
+                Err(e) => return Err(e),
+            }
+        }
+        if !self.to_stream.is_empty() {
+            match self.stream.write(&self.to_stream) {
+                Ok(n) => {
+                    self.to_stream.drain(..n);
+                    progress |= n > 0;
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {},
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(progress)
+    }
+
+    /// The database closed its side and everything it sent was passed on.
+    fn is_done(&self) -> bool {
+        self.remote_eof && self.to_stream.is_empty()
+    }
+}
+
+// A connection's tunnel, locked while it opens
+type TunnelSlot = Arc<tokio::sync::Mutex<Option<Arc<Tunnel>>>>;
+
+/// One tunnel per stored connection, shared by its session and pooled clients.
+pub struct TunnelManager {
+    tunnels: Mutex<HashMap<String, TunnelSlot>>,
+}
+
+impl TunnelManager {
+    pub fn new() -> Self {
+        TunnelManager {
+            tunnels: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The connection's tunnel, opened if there is none or the old one died.
+    pub async fn get(
+        &self,
+        connection_id: &str,
+        config: &SshTunnel,
+        remote_host: &str,
+        remote_port: u16,
+    ) -> Result<Arc<Tunnel>, AppError> {
+        let slot = self.tunnels.lock().unwrap()
+            .entry(connection_id.to_string())
+            .or_default()
+            .clone();
+        // Held across the open so concurrent pool connects share one tunnel,
+        // while other connections' tunnels open in the meantime
+        let mut tunnel = slot.lock().await;
+        if let Some(tunnel) = tunnel.as_ref().filter(|tunnel| tunnel.is_alive()) {
+            return Ok(tunnel.clone());
+        }
+
+        let opened = Arc::new(Tunnel::open(config, remote_host, remote_port).await?);
+        *tunnel = Some(opened.clone());
+        Ok(opened)
+    }
+
+    /// Drop the tunnel, e.g. after the connection's settings changed. Clients
+    /// still using it keep it open until they disconnect, and one still being
+    /// opened is only handed to the clients already waiting for it.
+    pub fn evict(&self, connection_id: &str) {
+        self.tunnels.lock().unwrap().remove(connection_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::STANDARD;
+    use ssh2::KnownHostKeyFormat;
+
+    const KEY: &str = "AAAAC3NzaC1lZDI1NTE5AAAAIOJ6GpUA6q1nJ6unsiwuybmOil+jfhfS5+yRAYGBIv25";
+
+    #[test]
+    fn new_host_is_appended_without_rewriting_the_file() {
+        let dir = std::env::temp_dir().join(format!("queryowl-known-hosts-{}", std::process::id()));
+        let path = dir.join("known_hosts");
+        std::fs::create_dir_all(&dir).unwrap();
+        // A comment and a hashed entry, and no newline at the end
+        let existing = "# managed by hand\n|1|c2FsdA==|aGFzaA== ssh-ed25519 AAAA";
+        std::fs::write(&path, existing).unwrap();
+
+        let session = Session::new().unwrap();
+        let mut known_hosts = session.known_hosts().unwrap();
+        let key = STANDARD.decode(KEY).unwrap();
+        known_hosts.add("[bastion.example]:2222", &key, "added by QueryOwl", KnownHostKeyFormat::Ed25519).unwrap();
+        append_known_host(&known_hosts, "[bastion.example]:2222", &path).unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(written, format!("{}\n[bastion.example]:2222 ssh-ed25519 {} added by QueryOwl\n", existing, KEY));
+    }
+}
@@ -1,6 +1,8 @@
 import { writable, derived } from 'svelte/store';
 import { invoke } from '@tauri-apps/api/core';
-import type { DatabaseConnection, ConnectionStatus, CreateConnectionRequest, UpdateConnectionRequest, TestConnectionRequest, TestConnectionResponse } from '$lib/types/database';
+import type { DatabaseConnection, ConnectionStatus, TransactionStatus, CreateConnectionRequest, UpdateConnectionRequest, TestConnectionRequest, TestConnectionResponse, ImportSource, ImportPreview, BundleEntry, BundleImport, BundleConflictPolicy, ConnectionFilter, ConnectionSort } from '$lib/types/database';
+import { errorMessage, isLockedError, isTransactionError } from '$lib/utils/errors';
+import { markLocked } from '$lib/stores/encryption';
 
 export const connections = writable<DatabaseConnection[]>([]);
 export const connectionStatus = writable<ConnectionStatus>({
@@ -9,6 +11,8 @@ export const connectionStatus = writable<ConnectionStatus>({
 	error: undefined
 });
 
+export const transactionStatus = writable<TransactionStatus>('idle');
+
 export const activeConnection = derived(
 	connectionStatus,
 	($status) => $status.activeConnection
@@ -19,9 +23,9 @@ export const isConnected = derived(
 	($status) => $status.isConnected
 );
 
-export async function loadConnections(): Promise<void> {
+export async function loadConnections(filter?: ConnectionFilter, sort?: ConnectionSort): Promise<void> {
 	try {
-		const stored = await invoke<DatabaseConnection[]>('get_stored_connections');
+		const stored = await invoke<DatabaseConnection[]>('get_stored_connections', { filter: filter ?? null, sort: sort ?? null });
 		connections.set(stored);
 	} catch (error) {
 		console.error('Failed to load connections:', error);
@@ -40,6 +44,40 @@ export async function saveConnection(connection: CreateConnectionRequest): Promi
 	}
 }
 
+export async function previewConnectionImport(source: ImportSource): Promise<ImportPreview> {
+	return await invoke<ImportPreview>('preview_connection_import', { source });
+}
+
+export async function importConnections(requests: CreateConnectionRequest[]): Promise<DatabaseConnection[]> {
+	try {
+		const imported = await invoke<DatabaseConnection[]>('import_connections', { connections: requests });
+		connections.update(conns => [...conns, ...imported]);
+		return imported;
+	} catch (error) {
+		console.error('Failed to import connections:', error);
+		throw error;
+	}
+}
+
+export async function exportConnections(path: string, ids: string[], passphrase: string, includePasswords: boolean): Promise<number> {
+	return await invoke<number>('export_connections', { path, ids, passphrase, includePasswords });
+}
+
+export async function previewConnectionBundle(path: string, passphrase: string): Promise<BundleEntry[]> {
+	return await invoke<BundleEntry[]>('preview_connection_bundle', { path, passphrase });
+}
+
+export async function importConnectionBundle(path: string, passphrase: string, conflict: BundleConflictPolicy): Promise<BundleImport> {
+	try {
+		const result = await invoke<BundleImport>('import_connection_bundle', { path, passphrase, conflict });
+		await loadConnections();
+		return result;
+	} catch (error) {
+		console.error('Failed to import connection bundle:', error);
+		throw error;
+	}
+}
+
 export async function updateConnection(connection: UpdateConnectionRequest): Promise<DatabaseConnection> {
 	try {
 		const updated = await invoke<DatabaseConnection>('update_connection', { connection });
@@ -75,13 +113,14 @@ export async function testConnection(connection: TestConnectionRequest): Promise
 		return await invoke<TestConnectionResponse>('test_database_connection', { connection });
 	} catch (error) {
 		console.error('Failed to test connection:', error);
-		return { success: false, error: String(error) };
+		return { success: false, error: { kind: 'connection', message: errorMessage(error) } };
 	}
 }
 
 export async function connectToDatabase(connection: DatabaseConnection): Promise<void> {
 	try {
-		await invoke('connect_to_database', { connection });
+		await invoke('connect_to_database', { connectionId: connection.id });
+		transactionStatus.set('idle');
 		connectionStatus.set({
 			isConnected: true,
 			activeConnection: connection,
@@ -94,18 +133,22 @@ export async function connectToDatabase(connection: DatabaseConnection): Promise
 		);
 	} catch (error) {
 		console.error('Failed to connect to database:', error);
+		if (isLockedError(error)) {
+			markLocked();
+		}
 		connectionStatus.set({
 			isConnected: false,
 			activeConnection: undefined,
-			error: String(error)
+			error: errorMessage(error)
 		});
 		throw error;
 	}
 }
 
-export async function disconnectFromDatabase(): Promise<void> {
+export async function disconnectFromDatabase(force = false): Promise<void> {
 	try {
-		await invoke('disconnect_from_database');
+		await invoke('disconnect_from_database', { force });
+		transactionStatus.set('idle');
 		connectionStatus.set({
 			isConnected: false,
 			activeConnection: undefined,
@@ -115,4 +158,51 @@ export async function disconnectFromDatabase(): Promise<void> {
 		console.error('Failed to disconnect from database:', error);
 		throw error;
 	}
+}
+
+// The backend refuses to drop an open transaction unasked; confirm and force it.
+// Resolves to whether the session was closed.
+export async function disconnectWithConfirmation(): Promise<boolean> {
+	try {
+		await disconnectFromDatabase();
+		return true;
+	} catch (error) {
+		if (!isTransactionError(error)) {
+			throw error;
+		}
+		if (!confirm('A transaction is still open. Disconnecting will roll it back. Disconnect anyway?')) {
+			return false;
+		}
+		await disconnectFromDatabase(true);
+		return true;
+	}
+}
+
+export async function refreshTransactionStatus(): Promise<void> {
+	try {
+		transactionStatus.set(await invoke<TransactionStatus>('get_transaction_status'));
+	} catch (error) {
+		console.error('Failed to get transaction status:', error);
+	}
+}
+
+export async function beginTransaction(): Promise<void> {
+	await invoke('begin_transaction', { isolationLevel: null, readOnly: false });
+}
+
+export async function commitTransaction(): Promise<void> {
+	try {
+		await invoke('commit_transaction');
+	} finally {
+		// A failed transaction is rolled back by COMMIT, so the status changes either way
+		await refreshTransactionStatus();
+	}
+}
+
+export async function rollbackTransaction(): Promise<void> {
+	try {
+		await invoke('rollback_transaction');
+	} finally {
+		await refreshTransactionStatus();
+	}
 }
\ No newline at end of file
@@ -0,0 +1,50 @@
+import { writable } from 'svelte/store';
+import { invoke } from '@tauri-apps/api/core';
+import type { EncryptionStatus } from '$lib/types/database';
+
+export const encryptionStatus = writable<EncryptionStatus>({
+	master_password: false,
+	locked: false,
+	auto_lock_minutes: null
+});
+
+export async function loadEncryptionStatus(): Promise<void> {
+	try {
+		encryptionStatus.set(await invoke<EncryptionStatus>('get_encryption_status'));
+	} catch (error) {
+		console.error('Failed to load encryption status:', error);
+	}
+}
+
+export function markLocked(): void {
+	encryptionStatus.update(status => ({ ...status, locked: true }));
+}
+
+export async function unlockConnections(password: string): Promise<void> {
+	await invoke('unlock_connections', { password });
+	await loadEncryptionStatus();
+}
+
+export async function lockConnections(): Promise<void> {
+	await invoke('lock_connections');
+	await loadEncryptionStatus();
+}
+
+export async function setMasterPassword(newPassword: string, currentPassword?: string): Promise<void> {
+	await invoke('set_master_password', { currentPassword: currentPassword || null, newPassword });
+	await loadEncryptionStatus();
+}
+
+export async function removeMasterPassword(currentPassword: string): Promise<void> {
+	await invoke('remove_master_password', { currentPassword });
+	await loadEncryptionStatus();
+}
+
+export async function setAutoLock(minutes: number | null): Promise<void> {
+	await invoke('set_auto_lock', { minutes });
+	await loadEncryptionStatus();
+}
+
+export async function rotateMasterKey(): Promise<void> {
+	await invoke('rotate_master_key');
+}
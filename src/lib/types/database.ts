@@ -1,4 +1,47 @@
-export interface DatabaseConnection {
+// Optional connection parameters; unset values use the server-side defaults
+export interface ConnectionOptions {
+	application_name?: string;
+	connect_timeout?: number;
+	keepalives?: boolean;
+	keepalives_idle?: number;
+	target_session_attrs?: 'any' | 'read-write' | 'read-only';
+	options?: string;
+	// Unset means confirm-destructive for production connections, unrestricted otherwise
+	safety_policy?: SafetyPolicy;
+}
+
+export type SafetyPolicy = 'read_only' | 'confirm_destructive' | 'unrestricted';
+
+// Bastion the database is reached through; secrets are stored encrypted
+export interface SshTunnel {
+	host: string;
+	port?: number;
+	username: string;
+	password?: string;
+	private_key_path?: string;
+	passphrase?: string;
+	known_hosts_path?: string;
+	trust_new_host?: boolean;
+}
+
+export type Environment = 'development' | 'staging' | 'production';
+
+// How a connection is organized in the list; folder is a '/'-separated path
+export interface ConnectionLabels {
+	folder?: string;
+	tags?: string[];
+	environment?: Environment;
+}
+
+export interface ConnectionFilter {
+	folder?: string;
+	tag?: string;
+	environment?: Environment;
+}
+
+export type ConnectionSort = 'name' | 'folder' | 'last_connected';
+
+export interface DatabaseConnection extends ConnectionOptions, ConnectionLabels {
 	id: string;
 	name: string;
 	host: string;
@@ -10,6 +53,7 @@ export interface DatabaseConnection {
 	createdAt: string;
 	lastConnected?: string;
 	color?: string;
+	ssh_tunnel?: SshTunnel;
 }
 
 export interface ConnectionStatus {
@@ -18,7 +62,10 @@ export interface ConnectionStatus {
 	error?: string;
 }
 
-export interface CreateConnectionRequest {
+// Transaction state of the live session, pushed by the `transaction-status` event
+export type TransactionStatus = 'idle' | 'in_transaction' | 'failed';
+
+export interface CreateConnectionRequest extends ConnectionOptions, ConnectionLabels {
 	name: string;
 	host: string;
 	port: number;
@@ -27,9 +74,10 @@ export interface CreateConnectionRequest {
 	password: string;
 	ssl?: boolean;
 	color?: string;
+	ssh_tunnel?: SshTunnel;
 }
 
-export interface UpdateConnectionRequest {
+export interface UpdateConnectionRequest extends ConnectionOptions, ConnectionLabels {
 	id: string;
 	name: string;
 	host: string;
@@ -39,18 +87,94 @@ export interface UpdateConnectionRequest {
 	password: string;
 	ssl?: boolean;
 	color?: string;
+	ssh_tunnel?: SshTunnel;
 }
 
-export interface TestConnectionRequest {
+export interface TestConnectionRequest extends ConnectionOptions {
 	host: string;
 	port: number;
 	database: string;
 	username: string;
 	password: string;
 	ssl?: boolean;
+	ssh_tunnel?: SshTunnel;
+}
+
+export type ImportSource =
+	| { kind: 'uri'; uri: string }
+	| { kind: 'pgpass'; path?: string }
+	| { kind: 'service_file'; path?: string };
+
+// Connections an import would create; passwords are still in plain text
+export interface ImportPreview {
+	connections: CreateConnectionRequest[];
+	skipped: string[];
+}
+
+export type BundleConflictPolicy = 'skip' | 'overwrite' | 'duplicate';
+
+// A connection in an encrypted bundle, before importing it
+export interface BundleEntry {
+	id: string;
+	name: string;
+	host: string;
+	database: string;
+	has_password: boolean;
+	conflicts_with?: string;
+}
+
+export interface BundleImport {
+	imported: DatabaseConnection[];
+	overwritten: string[];
+	skipped: string[];
+}
+
+export type AppErrorKind =
+	| 'not_found'
+	| 'invalid_input'
+	| 'storage'
+	| 'encryption'
+	| 'locked'
+	| 'tls'
+	| 'connection'
+	| 'authentication'
+	| 'tunnel'
+	| 'query'
+	| 'transaction'
+	| 'io';
+
+// Rejection value of every database command; `query` errors also carry the server's error fields
+export interface AppError {
+	kind: AppErrorKind;
+	message: string;
+	code?: string | null;
+	position?: number | null;
+	[field: string]: unknown;
 }
 
 export interface TestConnectionResponse {
 	success: boolean;
-	error?: string;
-}
\ No newline at end of file
+	error?: AppError;
+}
+export interface QueryColumn {
+	name: string;
+	ordinal: number;
+	type_name: string;
+	type_oid: number;
+	table_oid: number | null;
+	attnum: number | null;
+}
+
+export interface QueryNotice {
+	severity: string;
+	code: string;
+	message: string;
+	detail: string | null;
+	hint: string | null;
+}
+
+export interface EncryptionStatus {
+	master_password: boolean;
+	locked: boolean;
+	auto_lock_minutes?: number | null;
+}
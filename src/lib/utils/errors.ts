@@ -0,0 +1,19 @@
+import type { AppError } from '$lib/types/database';
+
+// Commands reject with an AppError object; anything else (e.g. a JS exception) is stringified
+export function errorMessage(error: unknown): string {
+	if (error && typeof error === 'object' && 'message' in error) {
+		return String((error as AppError).message);
+	}
+	return String(error);
+}
+
+// Saved connections are locked behind the master password
+export function isLockedError(error: unknown): boolean {
+	return !!error && typeof error === 'object' && (error as AppError).kind === 'locked';
+}
+
+// The command was refused because of the session's transaction, e.g. disconnecting while one is open
+export function isTransactionError(error: unknown): boolean {
+	return !!error && typeof error === 'object' && (error as AppError).kind === 'transaction';
+}